@@ -0,0 +1,155 @@
+//! Timings for the specialised queries against the plain way of getting the same answer.
+//! no bench framework, each case runs a fixed number of times and prints the mean, run with
+//! `cargo bench --bench quadtree`
+
+use quadtree_simple::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+fn lcg(seed: &mut u32) -> f32 {
+    *seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+    (*seed >> 8) as f32 / (1 << 24) as f32
+}
+
+fn random_points(n: usize, seed: u32) -> Vec<Point<usize>> {
+    let mut seed = seed;
+    (0..n).map(|i| Point::new(lcg(&mut seed) * 1000., lcg(&mut seed) * 1000., i)).collect()
+}
+
+fn random_tree(n: usize, seed: u32) -> Quadtree<usize> {
+    let mut qt = Quadtree::new(Qrect::screen_size(1000., 1000.), 8);
+    for point in random_points(n, seed) {
+        qt.insert(&point);
+    }
+    qt
+}
+
+// mean time of one call to f over iters calls
+fn time<R>(iters: u32, mut f: impl FnMut() -> R) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iters {
+        black_box(f());
+    }
+    start.elapsed() / iters
+}
+
+fn report(name: &str, fast: Duration, slow: Duration, slow_name: &str) {
+    println!(
+        "{name:<40} {fast:>12?}   {slow_name:<24} {slow:>12?}   {:.1}x",
+        slow.as_secs_f64() / fast.as_secs_f64()
+    );
+}
+
+fn intersects_any_point_vs_count_in_rect() {
+    let qt = random_tree(100_000, 1);
+    let mut seed = 2;
+    let ranges: Vec<Qrect> = (0..1000)
+        .map(|_| Qrect::new(lcg(&mut seed) * 1000., lcg(&mut seed) * 1000., 20., 20.))
+        .collect();
+
+    let fast = time(20, || ranges.iter().filter(|range| qt.intersects_any_point(range)).count());
+    let slow = time(20, || ranges.iter().filter(|range| qt.count_in_rect(range) > 0).count());
+    report("intersects_any_point", fast, slow, "count_in_rect > 0");
+}
+
+fn collect_by_x_range_vs_linear_scan() {
+    let qt = random_tree(100_000, 3);
+    let points = qt.collect();
+    let mut seed = 4;
+    let starts: Vec<f32> = (0..200).map(|_| lcg(&mut seed) * 990.).collect();
+
+    let fast = time(20, || starts.iter().map(|&x| qt.collect_by_x_range(x, x + 2.).len()).sum::<usize>());
+    let slow = time(20, || {
+        starts.iter()
+            .map(|&x| points.iter().filter(|p| p.x >= x && p.x <= x + 2.).cloned().collect::<Vec<_>>().len())
+            .sum::<usize>()
+    });
+    report("collect_by_x_range", fast, slow, "linear scan");
+
+    let fast = time(20, || starts.iter().map(|&y| qt.collect_by_y_range(y, y + 2.).len()).sum::<usize>());
+    let slow = time(20, || {
+        starts.iter()
+            .map(|&y| points.iter().filter(|p| p.y >= y && p.y <= y + 2.).cloned().collect::<Vec<_>>().len())
+            .sum::<usize>()
+    });
+    report("collect_by_y_range", fast, slow, "linear scan");
+}
+
+fn bulk_load_vs_insert_query_throughput() {
+    let points = random_points(100_000, 5);
+    let boundary = Qrect::screen_size(1000., 1000.);
+
+    let build_bulk = time(5, || Quadtree::bulk_load(points.clone(), boundary.clone(), 8));
+    let build_insert = time(5, || {
+        let mut qt = Quadtree::new(boundary.clone(), 8);
+        for point in &points {
+            qt.insert(point);
+        }
+        qt
+    });
+    report("bulk_load build", build_bulk, build_insert, "insert build");
+
+    let bulk = Quadtree::bulk_load(points.clone(), boundary.clone(), 8);
+    let inserted = random_tree(100_000, 5);
+    let mut seed = 6;
+    let ranges: Vec<Qrect> = (0..1000)
+        .map(|_| Qrect::new(lcg(&mut seed) * 1000., lcg(&mut seed) * 1000., 15., 15.))
+        .collect();
+
+    let fast = time(20, || ranges.iter().map(|range| bulk.query_rect(range).len()).sum::<usize>());
+    let slow = time(20, || ranges.iter().map(|range| inserted.query_rect(range).len()).sum::<usize>());
+    report("query_rect on bulk_load tree", fast, slow, "on insert tree");
+}
+
+fn point_exists_near_vs_circle_queries() {
+    let qt = random_tree(100_000, 7);
+    let stored = qt.collect();
+    let mut seed = 8;
+    // half exact lookups of stored points, half random spots that mostly miss
+    let queries: Vec<(f32, f32)> = (0..2000)
+        .map(|i| if i % 2 == 0 { (stored[i * 37].x, stored[i * 37].y) } else { (lcg(&mut seed) * 1000., lcg(&mut seed) * 1000.) })
+        .collect();
+
+    for epsilon in [0.01, 0.5] {
+        let fast = time(20, || queries.iter().filter(|&&(x, y)| qt.point_exists_near(x, y, epsilon)).count());
+        let any = time(20, || queries.iter().filter(|&&(x, y)| qt.intersects_any_circle(x, y, epsilon)).count());
+        let slow = time(20, || queries.iter().filter(|&&(x, y)| !qt.query_circle(x, y, epsilon).is_empty()).count());
+        report(&format!("point_exists_near eps {}", epsilon), fast, any, "intersects_any_circle");
+        report(&format!("point_exists_near eps {}", epsilon), fast, slow, "query_circle non empty");
+    }
+}
+
+fn adjacency_list_vs_brute_force() {
+    let points = random_points(1000, 9);
+    let mut qt = Quadtree::new(Qrect::screen_size(1000., 1000.), 8);
+    for point in &points {
+        qt.insert(point);
+    }
+    let radius = 30.;
+
+    let fast = time(20, || qt.to_adjacency_list(radius));
+    let slow = time(20, || {
+        points.iter()
+            .map(|a| {
+                let neighbours: Vec<Point<usize>> = points.iter()
+                    .filter(|b| b.data != a.data && (a.x - b.x).powi(2) + (a.y - b.y).powi(2) < radius * radius)
+                    .cloned()
+                    .collect();
+                (a.clone(), neighbours)
+            })
+            .collect::<Vec<_>>()
+    });
+    report("to_adjacency_list 1000 points", fast, slow, "brute force");
+
+    let fast = time(20, || qt.edge_count(radius));
+    let slow = time(20, || qt.to_adjacency_list(radius).iter().map(|(_, list)| list.len()).sum::<usize>() / 2);
+    report("edge_count 1000 points", fast, slow, "adjacency list degrees");
+}
+
+fn main() {
+    intersects_any_point_vs_count_in_rect();
+    collect_by_x_range_vs_linear_scan();
+    bulk_load_vs_insert_query_throughput();
+    point_exists_near_vs_circle_queries();
+    adjacency_list_vs_brute_force();
+}
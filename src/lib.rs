@@ -49,7 +49,10 @@
 //! `quadtree_simple` is licensed under the MIT license. See [LICENSE](LICENSE) for more details.
 //!
 
-
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 
 /// A point in 2D space with that holds some data
@@ -58,16 +61,29 @@ pub struct Point<T: Clone> {
     pub x: f32,
     pub y: f32,
     pub data: T,
+    // z layer the point sits on, 0 unless inserted with insert_layered
+    layer: u8,
 }
 impl<T: Clone> Point<T> {
     pub fn new(x: f32, y: f32, data: T) -> Self {
-        Self { x, y, data }
+        Self { x, y, data, layer: 0 }
+    }
+
+    /// the z layer the point sits on, 0 unless inserted with insert_layered
+    pub fn layer(&self) -> u8 {
+        self.layer
+    }
+}
+// the layer is a tag, two points with the same position and data are the same point
+impl<T: Clone + PartialEq> PartialEq for Point<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.x == other.x && self.y == other.y && self.data == other.data
     }
 }
 
 
 /// A rectangle anchored on center x, y with width w and height h
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Qrect {
     pub x: f32,
     pub y: f32,
@@ -95,22 +111,147 @@ impl Qrect {
         Self { x: width / 2., y: height / 2., w: width / 2., h: height / 2. }
     }
 
+    pub fn from_min_max(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Self {
+        Self::corners((min_x, min_y), (max_x, max_y))
+    }
+
+    pub fn min_x(&self) -> f32 { self.x - self.w }
+    pub fn max_x(&self) -> f32 { self.x + self.w }
+    pub fn min_y(&self) -> f32 { self.y - self.h }
+    pub fn max_y(&self) -> f32 { self.y + self.h }
+
+    /// the axis aligned rect that bounds this rect after rotating it by angle_rad around (cx, cy)
+    pub fn obb_aabb(&self, cx: f32, cy: f32, angle_rad: f32) -> Qrect {
+        let (sin, cos) = angle_rad.sin_cos();
+        let corners = [
+            (self.x - self.w, self.y - self.h),
+            (self.x + self.w, self.y - self.h),
+            (self.x - self.w, self.y + self.h),
+            (self.x + self.w, self.y + self.h),
+        ];
+
+        let mut min = (f32::MAX, f32::MAX);
+        let mut max = (f32::MIN, f32::MIN);
+        for (x, y) in corners {
+            let dx = x - cx;
+            let dy = y - cy;
+            let rx = cx + dx * cos - dy * sin;
+            let ry = cy + dx * sin + dy * cos;
+            min = (min.0.min(rx), min.1.min(ry));
+            max = (max.0.max(rx), max.1.max(ry));
+        }
+
+        Qrect::corners(min, max)
+    }
+
+    /// grow the rect by x_pad on the left and right and y_pad on the top and bottom
+    pub fn pad(&self, x_pad: f32, y_pad: f32) -> Qrect {
+        Qrect::new(self.x, self.y, self.w + x_pad, self.h + y_pad)
+    }
+
+    /// shrink the rect by x_inset on the left and right and y_inset on the top and bottom, never below zero size
+    pub fn inset(&self, x_inset: f32, y_inset: f32) -> Qrect {
+        Qrect::new(self.x, self.y, (self.w - x_inset).max(0.), (self.h - y_inset).max(0.))
+    }
+
+    /// grow each side by its own amount, the center moves toward the side with more margin
+    pub fn margin_rect(&self, top: f32, right: f32, bottom: f32, left: f32) -> Qrect {
+        Qrect::corners(
+            (self.x - self.w - left, self.y - self.h - top),
+            (self.x + self.w + right, self.y + self.h + bottom),
+        )
+    }
+
+    /// true if this rect overlaps the axis aligned bounding box of the polygon
+    pub fn intersects_polygon_aabb(&self, vertices: &[(f32, f32)]) -> bool {
+        match polygon_aabb(vertices) {
+            Some(aabb) => self.intersects_rect(&aabb),
+            None => false,
+        }
+    }
+
+    /// true if none of the fields are NaN or infinite
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.w.is_finite() && self.h.is_finite()
+    }
+
+    /// The world space rect shown by this rect, a viewport in screen space, when screen displays world
+    pub fn viewport_to_world(&self, world: &Qrect, screen: &Qrect) -> Qrect {
+        self.rescale(screen, world)
+    }
+
     fn contains_point<T: Clone>(&self, p: &Point<T>) -> bool {
-        return p.x >= self.x - self.w &&
+        p.x >= self.x - self.w &&
             p.x <= self.x + self.w &&
             p.y >= self.y - self.h &&
             p.y <= self.y + self.h
     }
 
+    fn contains_rect(&self, other: &Qrect) -> bool {
+        other.x - other.w >= self.x - self.w &&
+            other.x + other.w <= self.x + self.w &&
+            other.y - other.h >= self.y - self.h &&
+            other.y + other.h <= self.y + self.h
+    }
+
+    // map this rect from the space of one rect into the space of another
+    fn rescale(&self, from: &Qrect, to: &Qrect) -> Qrect {
+        let sx = to.w / from.w;
+        let sy = to.h / from.h;
+        Qrect::new(to.x + (self.x - from.x) * sx, to.y + (self.y - from.y) * sy, self.w * sx, self.h * sy)
+    }
+
+    // squared distance from (x, y) to the closest part of the rect, 0 if inside
+    fn distance_sq_to(&self, x: f32, y: f32) -> f32 {
+        let dx = ((self.x - self.w) - x).max(x - (self.x + self.w)).max(0.);
+        let dy = ((self.y - self.h) - y).max(y - (self.y + self.h)).max(0.);
+        dx * dx + dy * dy
+    }
+
+    // squared distance between the closest parts of two rects, 0 if they touch or overlap
+    fn gap_sq_to(&self, other: &Qrect) -> f32 {
+        let dx = ((self.x - other.x).abs() - (self.w + other.w)).max(0.);
+        let dy = ((self.y - other.y).abs() - (self.h + other.h)).max(0.);
+        dx * dx + dy * dy
+    }
+
     fn intersects_rect(&self, range: &Qrect) -> bool {
-        return !(range.x - range.w > self.x + self.w ||
-                range.x + range.w < self.x - self.w ||
-                range.y - range.h > self.y + self.h ||
-                range.y + range.h < self.y - self.h)
+        !(range.x - range.w > self.x + self.w ||
+            range.x + range.w < self.x - self.w ||
+            range.y - range.h > self.y + self.h ||
+            range.y + range.h < self.y - self.h)
+    }
+}
+/// (min, max) corners
+impl From<((f32, f32), (f32, f32))> for Qrect {
+    fn from((min, max): ((f32, f32), (f32, f32))) -> Self {
+        Qrect::corners(min, max)
+    }
+}
+/// (x, y, w, h) with w and h as half sizes, same as Qrect::new
+impl From<(f32, f32, f32, f32)> for Qrect {
+    fn from((x, y, w, h): (f32, f32, f32, f32)) -> Self {
+        Qrect::new(x, y, w, h)
+    }
+}
+impl From<&Qrect> for Qrect {
+    fn from(rect: &Qrect) -> Self {
+        rect.clone()
     }
 }
 
 
+// settings that only matter at the root, boxed so the other nodes carry a single null pointer
+#[derive(Clone, Default)]
+struct RootConfig {
+    // grid size inserted coordinates are snapped to
+    quantum: Option<f32>,
+    // balance_factor above which insert rebalances
+    auto_rebalance: Option<f32>,
+    // len when the tree was last rebalanced
+    rebuilt_len: usize,
+}
+
 /// A quadtree that can store points in 2D space
 #[derive(Clone)]
 pub struct Quadtree<T: Clone> {
@@ -123,6 +264,13 @@ pub struct Quadtree<T: Clone> {
     top_right: Option<Box<Quadtree<T>>>,
     bottom_left: Option<Box<Quadtree<T>>>,
     bottom_right: Option<Box<Quadtree<T>>>,
+
+    // settings only the root reads, None on every other node
+    config: Option<Box<RootConfig>>,
+    // stamped whenever this node's own points or children change
+    generation: u64,
+    // points held by this node and everything under it
+    count: usize,
 }
 impl<T: Clone> Quadtree<T> {
     /// create new quadtree
@@ -137,34 +285,134 @@ impl<T: Clone> Quadtree<T> {
             top_right: None,
             bottom_left: None,
             bottom_right: None,
+
+            config: None,
+            generation: next_generation(),
+            count: 0,
         }
     }
 
+    /// Snap the coordinates of every inserted point to a grid of size quantum.
+    /// this makes containment tests give the same answer on every platform for
+    /// inputs that only differ by float noise, at the cost of moving each point
+    /// by up to quantum / 2 so pick the coarsest grid your data can tolerate.
+    /// panics unless quantum is finite and positive
+    pub fn with_quantization(mut self, quantum: f32) -> Self {
+        assert!(quantum.is_finite() && quantum > 0., "quantum must be finite and positive, got {quantum}");
+        self.config.get_or_insert_with(Default::default).quantum = Some(quantum);
+        self
+    }
+
+    /// Rebalance after an insert that lands deeper than threshold times the ideal depth. off by default.
+    /// while it is on a full node whose points all share the new point's position takes it anyway, like
+    /// adaptive_insert, so stacked points never deepen the tree. a rebuild costs a pass over every point
+    /// so one only happens once len() has grown AUTO_REBALANCE_GROWTH times since the last, which keeps
+    /// the cost per insert constant on average. data that is deep by nature can sit above threshold
+    /// until then
+    pub fn with_auto_rebalance(mut self, threshold: f32) -> Self {
+        self.config.get_or_insert_with(Default::default).auto_rebalance = Some(threshold);
+        self
+    }
+
     /// Insert a point into the quadtree at the first possible location (x, y)
     pub fn insert(&mut self, point: &Point<T>) -> bool {
-        if !self.boundary.contains_point(&point) {
+        let point = self.prepare_point(point);
+        self.insert_root(&point)
+    }
+
+    fn quantum(&self) -> Option<f32> {
+        self.config.as_ref().and_then(|config| config.quantum)
+    }
+
+    fn auto_rebalance_threshold(&self) -> Option<f32> {
+        self.config.as_ref().and_then(|config| config.auto_rebalance)
+    }
+
+    // the point as the root will store it, every way of adding points goes through this
+    fn prepare_point<'a>(&self, point: &'a Point<T>) -> Cow<'a, Point<T>> {
+        match self.quantum() {
+            Some(quantum) => {
+                let mut snapped = point.clone();
+                snapped.x = (point.x / quantum).round() * quantum;
+                snapped.y = (point.y / quantum).round() * quantum;
+                Cow::Owned(snapped)
+            }
+            None => Cow::Borrowed(point),
+        }
+    }
+
+    // whether a point that just landed at depth should trigger an auto rebalance
+    fn auto_rebalance_due(&self, depth: usize) -> bool {
+        let Some(config) = &self.config else { return false };
+        config.auto_rebalance.is_some_and(|threshold| depth as f32 > threshold * self.ideal_depth() as f32)
+            && self.count >= config.rebuilt_len.saturating_mul(AUTO_REBALANCE_GROWTH)
+    }
+
+    fn insert_root(&mut self, point: &Point<T>) -> bool {
+        if self.auto_rebalance_threshold().is_none() {
+            return self.insert_node(point)
+        }
+
+        let mut path = vec![];
+        if !self.insert_node_with_path(point, &mut path, true) {
+            return false
+        }
+        // only the new point can have pushed the depth up
+        if self.auto_rebalance_due(path.len()) {
+            self.rebalance();
+        }
+        true
+    }
+
+    // the depth a tree with len points would need if every leaf was full, at least 1
+    fn ideal_depth(&self) -> usize {
+        let mut depth = 0;
+        let mut room = self.capacity.max(1);
+        while room < self.count {
+            room = room.saturating_mul(4);
+            depth += 1;
+        }
+        depth.max(1)
+    }
+
+    /// depth over the depth the point count calls for, around 1 when points are spread evenly
+    /// and growing as they bunch up
+    pub fn balance_factor(&self) -> f32 {
+        self.depth() as f32 / self.ideal_depth() as f32
+    }
+
+    fn insert_node(&mut self, point: &Point<T>) -> bool {
+        if !self.boundary.contains_point(point) {
             return false
         }
 
         if self.points.len() < self.capacity {
             self.points.push(point.clone());
-            return true
+            self.generation = next_generation();
+            self.count += 1;
+            true
         } else {
             if !self.divided {
                 self.subdivide();
             }
 
-            if self.top_left.as_mut().unwrap().insert(point) { return true }
-            if self.top_right.as_mut().unwrap().insert(point) { return true }
-            if self.bottom_left.as_mut().unwrap().insert(point) { return true }
-            if self.bottom_right.as_mut().unwrap().insert(point) { return true }
-
-            return false
+            let inserted = self.top_left.as_mut().unwrap().insert_node(point)
+                || self.top_right.as_mut().unwrap().insert_node(point)
+                || self.bottom_left.as_mut().unwrap().insert_node(point)
+                || self.bottom_right.as_mut().unwrap().insert_node(point);
+            if inserted {
+                self.count += 1;
+            }
+            inserted
         }
-
     }
 
     fn subdivide(&mut self) {
+        self.subdivide_into(self.quarters());
+    }
+
+    // the four equal cells subdivide splits this node into, in Quadrant::ALL order
+    fn quarters(&self) -> [Qrect; 4] {
         let x = self.boundary.x; let y = self.boundary.y;
         let w = self.boundary.w; let h = self.boundary.h;
 
@@ -172,41 +420,42 @@ impl<T: Clone> Quadtree<T> {
         let tl = Qrect::new(x - w / 2., y - h / 2., w / 2., h / 2.);
         let br = Qrect::new(x + w / 2., y + h / 2., w / 2., h / 2.);
         let bl = Qrect::new(x - w / 2., y + h / 2., w / 2., h / 2.);
+        [tl, tr, bl, br]
+    }
+
+    // whether the children, if any, are the ones subdivide would make
+    fn split_at_center(&self) -> bool {
+        !self.divided || self.children().map(|child| &child.boundary).eq(self.quarters().iter())
+    }
 
+    fn subdivide_into(&mut self, [tl, tr, bl, br]: [Qrect; 4]) {
         self.top_left = Some(Box::new(Quadtree::new(tl, self.capacity)));
         self.top_right = Some(Box::new(Quadtree::new(tr, self.capacity)));
         self.bottom_left = Some(Box::new(Quadtree::new(bl, self.capacity)));
         self.bottom_right = Some(Box::new(Quadtree::new(br, self.capacity)));
 
         self.divided = true;
+        self.generation = next_generation();
     }
 
-    /// Query the quadtree for points within a rectangle
-    pub fn query_rect(&self, range: &Qrect) -> Vec<Point<T>> {
+    /// Query the quadtree for points within a rectangle.
+    /// every point lives in exactly one node, even on a shared cell edge, so nothing is returned twice
+    /// range can be a Qrect, a &Qrect, ((min_x, min_y), (max_x, max_y)) or (x, y, w, h)
+    pub fn query_rect(&self, range: impl Into<Qrect>) -> Vec<Point<T>> {
+        let range = range.into();
+        // comparisons against NaN give nonsense so a bad range finds nothing
+        if !range.is_finite() {
+            return vec![]
+        }
+
         let mut found = vec![];
-        if !self.boundary.intersects_rect(range) {
-            return found
+        // an undivided tree is just a flat list, skip the boundary test and recursion
+        if !self.divided {
+            filter_in_rect(&self.points, &range, &mut found);
         } else {
-            for point in &self.points {
-                if range.contains_point(point) {
-                    found.push(point.clone());
-                }
-            }
-
-            if self.divided {
-                let top_left_points = self.top_left.as_ref().unwrap().query_rect(range);
-                let top_right_points = self.top_right.as_ref().unwrap().query_rect(range);
-                let bottom_left_points = self.bottom_left.as_ref().unwrap().query_rect(range);
-                let bottom_right_points = self.bottom_right.as_ref().unwrap().query_rect(range);
-
-                found.extend(top_left_points);
-                found.extend(top_right_points);
-                found.extend(bottom_left_points);
-                found.extend(bottom_right_points);
-            }
+            self.query_rect_into(&range, &mut found);
         }
-
-        return found
+        found
     }
 
     /// Query the quadtree for points within a circle
@@ -221,11 +470,7 @@ impl<T: Clone> Quadtree<T> {
             let dist_x = point.x - x;
             let dist_y = point.y - y;
             let dist = dist_x * dist_x + dist_y * dist_y;
-            if dist < (range * range) {
-                true
-            } else {
-                false
-            }
+            dist < (range * range)
         });
 
         temp
@@ -250,7 +495,9 @@ impl<T: Clone> Quadtree<T> {
 
     /// empty the quadtree
     pub fn empty(&mut self) {
+        self.generation = next_generation();
         self.points.clear();
+        self.count = 0;
         self.divided = false;
         self.top_left = None;
         self.top_right = None;
@@ -258,37 +505,5378 @@ impl<T: Clone> Quadtree<T> {
         self.bottom_right = None;
     }
 
-}
+    fn children(&self) -> impl Iterator<Item = &Quadtree<T>> {
+        [&self.top_left, &self.top_right, &self.bottom_left, &self.bottom_right]
+            .into_iter()
+            .filter_map(|child| child.as_deref())
+    }
 
+    /// Lazily iterate over references to the points within a rectangle
+    pub fn iter_rect(&self, range: impl Into<Qrect>) -> RectIter<'_, T> {
+        let range = range.into();
+        let pending = if range.is_finite() { VecDeque::from([Pending::Node(self)]) } else { VecDeque::new() };
+        RectIter { range, pending }
+    }
 
-/// tests
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Lazily iterate over clones of the points within a rectangle
+    pub fn cloned_iter_rect(&self, range: &Qrect) -> impl Iterator<Item = Point<T>> + '_ {
+        self.iter_rect(range).cloned()
+    }
 
-    #[test]
-    fn it_works(){
-        let size = 50.0;
-        let mut qt = Quadtree::new(Qrect::new(size, size, size, size), 4);
-        qt.insert(&Point::new(25., 25., 0));
+    /// Query the quadtree for points within a rectangle, sorted by distance to a focus point
+    pub fn query_rect_sorted_by_distance(&self, range: &Qrect, focus: (f32, f32)) -> Vec<Point<T>> {
+        let mut found = self.query_rect(range);
+        found.sort_by(|a, b| {
+            let dist_a = (a.x - focus.0).powi(2) + (a.y - focus.1).powi(2);
+            let dist_b = (b.x - focus.0).powi(2) + (b.y - focus.1).powi(2);
+            dist_a.total_cmp(&dist_b)
+        });
+        found
     }
 
-    #[test]
-    fn insert_at_known_points() {
-        let size = 50.0;
-        let mut qt = Quadtree::new(Qrect::new(size, size, size, size), 4);
-        qt.insert(&Point::new(25., 25., 0));
-        qt.insert(&Point::new(25., 25., 1));
-        qt.insert(&Point::new(25., 25., 2));
-        qt.insert(&Point::new(25., 25., 3));
-        // check
-        let found = qt.query_rect(&Qrect::range(25., 25., 1.));
-        assert_eq!(found.len(), 4);
-        // check locations
-        assert_eq!(found[0].data, 0);
-        assert_eq!(found[1].data, 1);
-        assert_eq!(found[2].data, 2);
-        assert_eq!(found[3].data, 3);
+    /// Query the quadtree for points within a rect expressed in a frame rotated by angle_rad around (cx, cy)
+    pub fn query_in_rotated_frame(&self, cx: f32, cy: f32, angle_rad: f32, local_rect: &Qrect) -> Vec<Point<T>> {
+        // prefilter with the axis aligned box around the rotated rect
+        let mut temp = self.query_rect(local_rect.obb_aabb(cx, cy, angle_rad));
+
+        let (sin, cos) = (-angle_rad).sin_cos();
+        temp.retain(|point| {
+            let dx = point.x - cx;
+            let dy = point.y - cy;
+            let local = Point::new(cx + dx * cos - dy * sin, cy + dx * sin + dy * cos, ());
+            local_rect.contains_point(&local)
+        });
+
+        temp
+    }
+
+    /// Query this quadtree and another for points within the same region, returns (self_points, other_points)
+    pub fn points_in_other_region(&self, other: &Quadtree<T>, region: &Qrect) -> (Vec<Point<T>>, Vec<Point<T>>) {
+        (self.query_rect(region), other.query_rect(region))
+    }
+
+    // drop the children once none of them hold anything
+    fn collapse_empty_children(&mut self) {
+        if self.divided && self.children().all(|child| !child.divided && child.points.is_empty()) {
+            self.divided = false;
+            self.generation = next_generation();
+            self.top_left = None;
+            self.top_right = None;
+            self.bottom_left = None;
+            self.bottom_right = None;
+        }
+    }
+
+    /// the depth of the deepest node, a tree that never subdivided has depth 0
+    pub fn depth(&self) -> usize {
+        self.children().map(|child| child.depth() + 1).max().unwrap_or(0)
+    }
+
+    /// Build a thinned copy keeping only the first point in each cell of a grid
+    /// factor times coarser than the deepest level of this tree
+    pub fn grid_downsample(&self, factor: usize) -> Quadtree<T> {
+        // a stack of near coincident points can go deeper than usize has bits
+        let finest = 1usize.checked_shl(self.depth() as u32).unwrap_or(usize::MAX);
+        let cells = (finest / factor.max(1)).max(1);
+        let cell_w = self.boundary.w * 2. / cells as f32;
+        let cell_h = self.boundary.h * 2. / cells as f32;
+        let left = self.boundary.x - self.boundary.w;
+        let top = self.boundary.y - self.boundary.h;
+
+        let mut taken = HashSet::new();
+        let mut downsampled = Quadtree::new(self.boundary.clone(), self.capacity);
+        for point in self.collect() {
+            let cx = (((point.x - left) / cell_w) as usize).min(cells - 1);
+            let cy = (((point.y - top) / cell_h) as usize).min(cells - 1);
+            if taken.insert((cx, cy)) {
+                downsampled.insert(&point);
+            }
+        }
+
+        downsampled
+    }
+
+    // push every point within range onto found
+    fn query_rect_into(&self, range: &Qrect, found: &mut Vec<Point<T>>) {
+        if !self.boundary.intersects_rect(range) {
+            return
+        }
+
+        filter_in_rect(&self.points, range, found);
+        for child in self.children() {
+            child.query_rect_into(range, found);
+        }
+    }
+
+    /// Query the quadtree for points within a rectangle, preallocating room for the expected result count
+    pub fn query_rect_with_hint(&self, range: &Qrect, expected: usize) -> Vec<Point<T>> {
+        let mut found = Vec::with_capacity(expected);
+        if range.is_finite() {
+            self.query_rect_into(range, &mut found);
+        }
+        found
+    }
+
+    /// the area covered by the quadtree
+    pub fn boundary(&self) -> &Qrect {
+        &self.boundary
+    }
+
+    /// Grow the boundary without losing points, the node layout is stretched to fit the new boundary
+    /// and any point that ends up outside its stretched node is inserted again from the top.
+    /// returns false and does nothing if new_boundary doesn't contain the current one
+    pub fn expand_boundary(&mut self, new_boundary: Qrect) -> bool {
+        if !new_boundary.contains_rect(&self.boundary) {
+            return false
+        }
+
+        let old_boundary = self.boundary.clone();
+        let mut displaced = vec![];
+        self.rescale_nodes(&old_boundary, &new_boundary, &mut displaced);
+
+        // points that no longer sit inside their stretched node go back in from the top
+        for point in &displaced {
+            self.insert(point);
+        }
+        true
+    }
+
+    fn rescale_nodes(&mut self, from: &Qrect, to: &Qrect, displaced: &mut Vec<Point<T>>) {
+        self.boundary = self.boundary.rescale(from, to);
+
+        let boundary = &self.boundary;
+        let (inside, outside): (Vec<_>, Vec<_>) = self.points.drain(..).partition(|point| boundary.contains_point(point));
+        self.points = inside;
+        self.generation = next_generation();
+        displaced.extend(outside);
+
+        for child in [&mut self.top_left, &mut self.top_right, &mut self.bottom_left, &mut self.bottom_right] {
+            if let Some(child) = child.as_mut() {
+                child.rescale_nodes(from, to, displaced);
+            }
+        }
+        self.recount();
+    }
+
+    // refresh the cached count from this node's points and its children's counts
+    fn recount(&mut self) {
+        self.count = self.points.len() + self.children().map(|child| child.count).sum::<usize>();
+    }
+
+    /// Build a quadtree that fits the bounding box of the points.
+    /// panics if points is empty since there is no bounding box
+    pub fn from_vec(points: Vec<Point<T>>, capacity: usize) -> Self {
+        let mut qt = Quadtree::new(bounding_box_of(&points).expect("from_vec needs at least one point"), capacity);
+        for point in &points {
+            qt.insert(point);
+        }
+        qt
+    }
+
+    /// number of points in the quadtree
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// true if the quadtree holds no points
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the smallest rect containing every point, None for an empty quadtree
+    pub fn bounding_box(&self) -> Option<Qrect> {
+        bounding_box_of(&self.collect())
+    }
+
+    /// Consume the quadtree and return all its points without cloning
+    pub fn into_vec(self) -> Vec<Point<T>> {
+        let mut out = self.points;
+        for child in [self.top_left, self.top_right, self.bottom_left, self.bottom_right].into_iter().flatten() {
+            out.extend(child.into_vec());
+        }
+        out
+    }
+
+    /// Find the nearest point to (x, y) that passes pred
+    pub fn nearest_where(&self, x: f32, y: f32, pred: impl Fn(&Point<T>) -> bool) -> Option<Point<T>> {
+        let mut best = None;
+        self.nearest_search(x, y, &pred, &mut best);
+        best.map(|(point, _)| point.clone())
+    }
+
+    /// Find the nearest point to (x, y) along with its distance
+    pub fn nearest_with_distance(&self, x: f32, y: f32) -> Option<(Point<T>, f32)> {
+        let mut best = None;
+        self.nearest_search(x, y, &|_| true, &mut best);
+        best.map(|(point, dist_sq)| (point.clone(), dist_sq.sqrt()))
+    }
+
+    // branch and bound search, nodes are visited closest first and skipped once
+    // they can't hold anything closer than the best found so far
+    fn nearest_search<'a>(&'a self, x: f32, y: f32, pred: &impl Fn(&Point<T>) -> bool, best: &mut Option<(&'a Point<T>, f32)>) {
+        if let Some((_, best_dist)) = best {
+            if self.boundary.distance_sq_to(x, y) >= *best_dist {
+                return
+            }
+        }
+
+        for point in &self.points {
+            let dist = (point.x - x).powi(2) + (point.y - y).powi(2);
+            if best.is_none_or(|(_, best_dist)| dist < best_dist) && pred(point) {
+                *best = Some((point, dist));
+            }
+        }
+
+        let mut children: Vec<&Quadtree<T>> = self.children().collect();
+        children.sort_by(|a, b| a.boundary.distance_sq_to(x, y).total_cmp(&b.boundary.distance_sq_to(x, y)));
+        for child in children {
+            child.nearest_search(x, y, pred, best);
+        }
+    }
+
+    /// Consume the quadtree and group its points by the bits of their exact coordinates
+    pub fn into_coordinate_map(self) -> HashMap<(u32, u32), Vec<Point<T>>> {
+        let mut map: HashMap<(u32, u32), Vec<Point<T>>> = HashMap::new();
+        for point in self.into_vec() {
+            map.entry((point.x.to_bits(), point.y.to_bits())).or_default().push(point);
+        }
+        map
+    }
+
+    /// Rebuild a quadtree from a map made by into_coordinate_map
+    pub fn from_coordinate_map(map: HashMap<(u32, u32), Vec<Point<T>>>, boundary: Qrect, capacity: usize) -> Self {
+        let mut qt = Quadtree::new(boundary, capacity);
+        for point in map.values().flatten() {
+            qt.insert(point);
+        }
+        qt
+    }
+
+    /// number of undivided nodes
+    pub fn leaf_count(&self) -> usize {
+        if !self.divided {
+            return 1
+        }
+        self.children().map(|child| child.leaf_count()).sum()
+    }
+
+    /// Subdivide every node down to depth, giving a uniform grid of 4^depth leaves
+    pub fn pre_subdivide(&mut self, depth: usize) {
+        if depth == 0 {
+            return
+        }
+        if !self.divided {
+            self.subdivide();
+        }
+        for child in [&mut self.top_left, &mut self.top_right, &mut self.bottom_left, &mut self.bottom_right] {
+            child.as_mut().unwrap().pre_subdivide(depth - 1);
+        }
+    }
+
+    /// Query the quadtree for points inside a polygon, which may be concave or self intersecting.
+    /// uses the even-odd ray casting rule, whether points exactly on an edge are included is undefined
+    pub fn query_polygon_approximate(&self, vertices: &[(f32, f32)]) -> Vec<Point<T>> {
+        if vertices.len() < 3 || !self.boundary.intersects_polygon_aabb(vertices) {
+            return vec![]
+        }
+
+        let mut temp = self.query_rect(polygon_aabb(vertices).unwrap());
+        temp.retain(|point| {
+            // count crossings of a ray going right from the point
+            let mut inside = false;
+            let mut j = vertices.len() - 1;
+            for i in 0..vertices.len() {
+                let (xi, yi) = vertices[i];
+                let (xj, yj) = vertices[j];
+                if (yi > point.y) != (yj > point.y) && point.x < (xj - xi) * (point.y - yi) / (yj - yi) + xi {
+                    inside = !inside;
+                }
+                j = i;
+            }
+            inside
+        });
+
+        temp
+    }
+
+    /// Query the quadtree for points within a rectangle taking at most max_per_leaf from each node,
+    /// spreads the result across space instead of favouring dense cells
+    pub fn query_rect_capped_per_leaf(&self, range: &Qrect, max_per_leaf: usize) -> Vec<Point<T>> {
+        let mut found = vec![];
+        if range.is_finite() {
+            self.query_rect_capped_into(range, max_per_leaf, &mut found);
+        }
+        found
+    }
+
+    fn query_rect_capped_into(&self, range: &Qrect, max_per_leaf: usize, found: &mut Vec<Point<T>>) {
+        if !self.boundary.intersects_rect(range) {
+            return
+        }
+
+        found.extend(self.points.iter().filter(|point| range.contains_point(point)).take(max_per_leaf).cloned());
+        for child in self.children() {
+            child.query_rect_capped_into(range, max_per_leaf, found);
+        }
+    }
+
+    /// Print the layout of the quadtree to stderr without touching the point data
+    pub fn debug_print_structure(&self) {
+        eprint!("{}", self.structure_string());
+    }
+
+    /// the layout of the quadtree as text, one node per line indented by depth
+    pub fn structure_string(&self) -> String {
+        let mut out = String::new();
+        self.write_structure(&mut out).expect("writing to a String can't fail");
+        out
+    }
+
+    /// Write the layout of the quadtree to out in the same format as structure_string
+    pub fn write_structure(&self, out: &mut impl std::fmt::Write) -> std::fmt::Result {
+        self.write_structure_node(out, 0, "")
+    }
+
+    fn write_structure_node(&self, out: &mut impl std::fmt::Write, depth: usize, label: &str) -> std::fmt::Result {
+        let b = &self.boundary;
+        let size = if b.w == b.h { format!("{}", b.w) } else { format!("{},{}", b.w, b.h) };
+        writeln!(
+            out,
+            "{}{}[depth={} boundary=({},{} ±{}) points={} divided={}]",
+            "  ".repeat(depth), label, depth, b.x, b.y, size, self.points.len(), self.divided
+        )?;
+
+        let labels = ["TL: ", "TR: ", "BL: ", "BR: "];
+        for (child, label) in self.children().zip(labels) {
+            child.write_structure_node(out, depth + 1, label)?;
+        }
+        Ok(())
+    }
+
+    /// Count the points whose data passes pred without cloning anything
+    pub fn count_where(&self, pred: impl Fn(&T) -> bool) -> usize {
+        self.count_where_ref(&pred)
+    }
+
+    fn count_where_ref(&self, pred: &impl Fn(&T) -> bool) -> usize {
+        self.points.iter().filter(|point| pred(&point.data)).count()
+            + self.children().map(|child| child.count_where_ref(pred)).sum::<usize>()
+    }
+
+    /// Query the quadtree for points at least inner and less than outer away from (x, y)
+    pub fn query_annulus(&self, x: f32, y: f32, inner: f32, outer: f32) -> Vec<Point<T>> {
+        let mut temp = self.query_circle(x, y, outer);
+        temp.retain(|point| (point.x - x).powi(2) + (point.y - y).powi(2) >= inner * inner);
+        temp
+    }
+
+    /// Split the distances start_r..end_r from (cx, cy) into n_rings equal width rings and
+    /// query each one, result[0] is the innermost ring
+    pub fn query_spiral(&self, cx: f32, cy: f32, start_r: f32, end_r: f32, n_rings: usize) -> Vec<Vec<Point<T>>> {
+        let width = (end_r - start_r) / n_rings as f32;
+        (0..n_rings).map(|i| {
+            let inner = start_r + width * i as f32;
+            let outer = if i + 1 == n_rings { end_r } else { start_r + width * (i + 1) as f32 };
+            self.query_annulus(cx, cy, inner, outer)
+        }).collect()
+    }
+
+    /// how many points a node holds before it subdivides
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// A copy of the quadtree with the same boundary and points but a different capacity
+    pub fn rebuilt_with_capacity(&self, capacity: usize) -> Quadtree<T> {
+        let mut qt = Quadtree::new(self.boundary.clone(), capacity);
+        for point in self.iter_rect(&self.boundary) {
+            qt.insert(point);
+        }
+        qt
+    }
+
+    /// true as soon as any point is found within range
+    pub fn intersects_any_point(&self, range: &Qrect) -> bool {
+        if !range.is_finite() {
+            return false
+        }
+
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if !node.boundary.intersects_rect(range) {
+                continue
+            }
+            if node.points.iter().any(|point| range.contains_point(point)) {
+                return true
+            }
+            stack.extend(node.children());
+        }
+        false
+    }
+
+    /// true as soon as any point is found less than r away from (cx, cy)
+    pub fn intersects_any_circle(&self, cx: f32, cy: f32, r: f32) -> bool {
+        let rect = Qrect::new(cx, cy, r, r);
+        if !rect.is_finite() {
+            return false
+        }
+
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.boundary.distance_sq_to(cx, cy) >= r * r {
+                continue
+            }
+            if node.points.iter().any(|point| (point.x - cx).powi(2) + (point.y - cy).powi(2) < r * r) {
+                return true
+            }
+            stack.extend(node.children());
+        }
+        false
+    }
+
+    /// Collect all points with x_min <= x <= x_max, whatever their y
+    pub fn collect_by_x_range(&self, x_min: f32, x_max: f32) -> Vec<Point<T>> {
+        let b = &self.boundary;
+        self.query_rect(Qrect::from_min_max(x_min, b.min_y(), x_max, b.max_y()))
+    }
+
+    /// Collect all points with y_min <= y <= y_max, whatever their x
+    pub fn collect_by_y_range(&self, y_min: f32, y_max: f32) -> Vec<Point<T>> {
+        let b = &self.boundary;
+        self.query_rect(Qrect::from_min_max(b.min_x(), y_min, b.max_x(), y_max))
+    }
+
+    /// total number of nodes including the root
+    pub fn node_count(&self) -> usize {
+        1 + self.children().map(|child| child.node_count()).sum::<usize>()
+    }
+
+    /// number of nodes exactly d levels below the root
+    pub fn count_nodes_at_depth(&self, d: usize) -> usize {
+        if d == 0 {
+            return 1
+        }
+        self.children().map(|child| child.count_nodes_at_depth(d - 1)).sum()
+    }
+
+    /// Check that undivided nodes have no children, divided nodes have all four, every cached
+    /// point count is right and every point sits inside the boundary of each node above it.
+    /// one pass over the tree so it's cheap enough for debug assertions
+    pub fn structural_check(&self) -> bool {
+        self.check_subtree().is_some()
+    }
+
+    // post order, each node hands back its point count and the [min_x, min_y, max_x, max_y]
+    // of its points so the parent can check them against its own count and boundary
+    fn check_subtree(&self) -> Option<(usize, [f32; 4])> {
+        let expected_children = if self.divided { 4 } else { 0 };
+        if self.children().count() != expected_children {
+            return None
+        }
+
+        let mut count = self.points.len();
+        let mut bounds = [f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY];
+        for point in &self.points {
+            bounds = [bounds[0].min(point.x), bounds[1].min(point.y), bounds[2].max(point.x), bounds[3].max(point.y)];
+        }
+        for child in self.children() {
+            let (child_count, b) = child.check_subtree()?;
+            count += child_count;
+            bounds = [bounds[0].min(b[0]), bounds[1].min(b[1]), bounds[2].max(b[2]), bounds[3].max(b[3])];
+        }
+
+        let b = &self.boundary;
+        let inside = count == 0
+            || (bounds[0] >= b.min_x() && bounds[1] >= b.min_y() && bounds[2] <= b.max_x() && bounds[3] <= b.max_y());
+        (count == self.count && inside).then_some((count, bounds))
+    }
+
+    /// the points stored directly in this node, not in its children
+    pub fn points(&self) -> &[Point<T>] {
+        &self.points
+    }
+
+    /// Iterate over every undivided node, note divided nodes hold points too
+    /// so the leaves alone don't cover every point
+    pub fn leaves(&self) -> impl Iterator<Item = &Quadtree<T>> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            while let Some(node) = stack.pop() {
+                if !node.divided {
+                    return Some(node)
+                }
+                let children: Vec<&Quadtree<T>> = node.children().collect();
+                stack.extend(children.into_iter().rev());
+            }
+            None
+        })
+    }
+
+    /// Pack (x, y) into a u64 key by mapping each axis of the boundary onto the full u32 range,
+    /// x goes in the high 32 bits
+    pub fn encode_position(&self, x: f32, y: f32) -> u64 {
+        let b = &self.boundary;
+        let qx = ((x - b.min_x()) / (b.w * 2.) * u32::MAX as f32) as u32;
+        let qy = ((y - b.min_y()) / (b.h * 2.) * u32::MAX as f32) as u32;
+        ((qx as u64) << 32) | qy as u64
+    }
+
+    /// Unpack a key made by encode_position back into a position, accurate to the quantization step
+    pub fn decode_position(&self, code: u64) -> (f32, f32) {
+        let b = &self.boundary;
+        let qx = (code >> 32) as u32;
+        let qy = code as u32;
+        (
+            b.min_x() + qx as f32 / u32::MAX as f32 * (b.w * 2.),
+            b.min_y() + qy as f32 / u32::MAX as f32 * (b.h * 2.),
+        )
+    }
+
+    /// Find the two closest points and the distance between them, None with fewer than 2 points.
+    /// runs a pruned nearest neighbour search from every point that starts from the best
+    /// distance found so far, O(n log n) on average and O(n^2) for badly clustered input
+    pub fn find_nearest_pair(&self) -> Option<(&Point<T>, &Point<T>, f32)> {
+        let mut best: Option<(&Point<T>, &Point<T>, f32)> = None;
+
+        for point in self.iter_rect(&self.boundary) {
+            let mut nearest = best.map(|(_, other, dist_sq)| (other, dist_sq));
+            self.nearest_search(point.x, point.y, &|other: &Point<T>| !std::ptr::eq(other, point), &mut nearest);
+
+            if let Some((other, dist_sq)) = nearest {
+                if best.is_none_or(|(_, _, best_dist)| dist_sq < best_dist) {
+                    best = Some((point, other, dist_sq));
+                }
+            }
+        }
+
+        best.map(|(a, b, dist_sq)| (a, b, dist_sq.sqrt()))
+    }
+
+    /// Insert a point, first pushing it away from any neighbour closer than min_sep.
+    /// returns where the point ended up, or None without inserting if no free spot turned up
+    /// within a bounded number of pushes or the spot is outside the boundary.
+    /// with quantization every candidate is snapped before it is tested, rounding away from the
+    /// neighbour it was pushed off, so the returned position is the one that gets stored
+    pub fn insert_non_overlapping(&mut self, point: &Point<T>, min_sep: f32) -> Option<(f32, f32)> {
+        let quantum = self.quantum();
+        let snap = |v: f32, dir: f32| match quantum {
+            Some(q) if dir > 0. => (v / q).ceil() * q,
+            Some(q) if dir < 0. => (v / q).floor() * q,
+            Some(q) => (v / q).round() * q,
+            None => v,
+        };
+        let (mut x, mut y) = (snap(point.x, 0.), snap(point.y, 0.));
+        // landing exactly min_sep away can round to just under it
+        let clear = min_sep * (1. - 1e-5);
+
+        // each push can land the point near another neighbour so repeat a bounded number of times,
+        // the nearest point being far enough means every point is
+        let mut settled = false;
+        for _ in 0..32 {
+            let Some(nearest) = self.nearest_where(x, y, |_| true) else {
+                settled = true;
+                break
+            };
+            let dx = x - nearest.x;
+            let dy = y - nearest.y;
+            let dist = (dx * dx + dy * dy).sqrt();
+            if dist >= clear {
+                settled = true;
+                break
+            }
+
+            let (dir_x, dir_y) = if dist > 0. { (dx / dist, dy / dist) } else { (1., 0.) };
+            x = snap(nearest.x + dir_x * min_sep, dir_x);
+            y = snap(nearest.y + dir_y * min_sep, dir_y);
+        }
+        if !settled {
+            return None
+        }
+
+        let mut moved = point.clone();
+        moved.x = x;
+        moved.y = y;
+        self.insert(&moved).then_some((x, y))
+    }
+
+    /// Collect up to max_points points less than radius from (cx, cy), closest first
+    pub fn moving_window_collect(&self, cx: f32, cy: f32, radius: f32, max_points: usize) -> Vec<Point<T>> {
+        let mut found = vec![];
+        if max_points > 0 {
+            self.k_nearest_search(cx, cy, max_points, radius * radius, &mut found);
+        }
+        found.into_iter().map(|(_, point)| point.clone()).collect()
+    }
+
+    /// The n closest points less than r from (cx, cy), closest first.
+    /// same result as query_circle then sort then take(n) but only ever holds n candidates
+    pub fn query_n_nearest_in_circle(&self, cx: f32, cy: f32, r: f32, n: usize) -> Vec<Point<T>> {
+        self.moving_window_collect(cx, cy, r, n)
+    }
+
+    /// The stored point whose voronoi cell holds (x, y), ie the nearest one. this is exact,
+    /// the branch and bound search behind nearest_where never skips a node that could hold a closer point
+    pub fn voronoi_cell_owner(&self, x: f32, y: f32) -> Option<&Point<T>> {
+        let mut best = None;
+        self.nearest_search(x, y, &|_| true, &mut best);
+        best.map(|(point, _)| point)
+    }
+
+    /// The n points nearest to (x, y), closest first, the candidate owners for approximate voronoi work
+    pub fn voronoi_n_candidates(&self, x: f32, y: f32, n: usize) -> Vec<&Point<T>> {
+        let mut found = vec![];
+        if n > 0 {
+            self.k_nearest_search(x, y, n, f32::INFINITY, &mut found);
+        }
+        found.into_iter().map(|(_, point)| point).collect()
+    }
+
+    // branch and bound k nearest search, found stays sorted by squared distance and never
+    // grows past k, nodes are skipped once they can't beat the current kth point
+    fn k_nearest_search<'a>(&'a self, x: f32, y: f32, k: usize, max_dist_sq: f32, found: &mut Vec<(f32, &'a Point<T>)>) {
+        let limit = if found.len() == k { found[k - 1].0 } else { max_dist_sq };
+        if self.boundary.distance_sq_to(x, y) >= limit {
+            return
+        }
+
+        for point in &self.points {
+            let dist = (point.x - x).powi(2) + (point.y - y).powi(2);
+            let limit = if found.len() == k { found[k - 1].0 } else { max_dist_sq };
+            if dist < limit {
+                let i = found.partition_point(|(d, _)| *d <= dist);
+                found.insert(i, (dist, point));
+                found.truncate(k);
+            }
+        }
+
+        let mut children: Vec<&Quadtree<T>> = self.children().collect();
+        children.sort_by(|a, b| a.boundary.distance_sq_to(x, y).total_cmp(&b.boundary.distance_sq_to(x, y)));
+        for child in children {
+            child.k_nearest_search(x, y, k, max_dist_sq, found);
+        }
+    }
+
+    // collapse from the bottom up so whole empty branches fold away
+    fn collapse_empty_subtrees(&mut self) {
+        for child in [&mut self.top_left, &mut self.top_right, &mut self.bottom_left, &mut self.bottom_right] {
+            if let Some(child) = child.as_mut() {
+                child.collapse_empty_subtrees();
+            }
+        }
+        self.collapse_empty_children();
+    }
+
+    /// Every point as a ([min_x, min_y, max_x, max_y], data) entry with a zero size box,
+    /// the layout rstar style bulk loaders take
+    pub fn to_entry_list(&self) -> Vec<([f32; 4], T)> {
+        self.iter_rect(&self.boundary)
+            .map(|point| ([point.x, point.y, point.x, point.y], point.data.clone()))
+            .collect()
+    }
+
+    /// Build a quadtree from ([min_x, min_y, max_x, max_y], data) entries using the center of each box.
+    /// panics if entries is empty, see from_vec
+    pub fn from_aabb_list(entries: Vec<([f32; 4], T)>, capacity: usize) -> Self {
+        let points = entries.into_iter()
+            .map(|([min_x, min_y, max_x, max_y], data)| Point::new((min_x + max_x) / 2., (min_y + max_y) / 2., data))
+            .collect();
+        Quadtree::from_vec(points, capacity)
+    }
+
+    /// Rebuild the tree from its own points the way bulk_load does, keeping the capacity.
+    /// points stacked on one position stay together in one leaf even past capacity since no
+    /// amount of subdividing can separate those
+    pub fn rebalance(&mut self) {
+        let points = self.collect();
+        self.empty();
+        self.bulk_load_node(points, 0);
+        if let Some(config) = &mut self.config {
+            config.rebuilt_len = self.count;
+        }
+    }
+
+    /// Insert a point reporting where it landed. a full node whose points all share the new
+    /// point's position takes it anyway instead of subdividing forever, and once a node holds
+    /// more than capacity * REBALANCE_THRESHOLD points that aren't all one stack it is rebalanced,
+    /// a stack is already as shallow as it can get. quantization and
+    /// with_auto_rebalance apply the same way they do for insert
+    pub fn adaptive_insert(&mut self, point: &Point<T>) -> InsertResult {
+        let point = self.prepare_point(point);
+        if !point.x.is_finite() || !point.y.is_finite() {
+            return InsertResult::Rejected { reason: InsertRejection::NonFinite }
+        }
+        if !self.boundary.contains_point(&point) {
+            return InsertResult::Rejected { reason: InsertRejection::OutOfBounds }
+        }
+
+        match self.adaptive_insert_at(&point, 0) {
+            InsertResult::Inserted { depth } if self.auto_rebalance_due(depth) => {
+                let old_node_count = self.node_count();
+                self.rebalance();
+                InsertResult::Rebalanced { depth, old_node_count, new_node_count: self.node_count() }
+            }
+            result => result,
+        }
+    }
+
+    fn adaptive_insert_at(&mut self, point: &Point<T>, depth: usize) -> InsertResult {
+        let stacked = !self.points.is_empty() && self.points.iter().all(|p| p.x == point.x && p.y == point.y);
+        if self.points.len() < self.capacity || stacked {
+            self.points.push(point.clone());
+            self.generation = next_generation();
+            self.count += 1;
+
+            if self.points.len() > self.capacity * REBALANCE_THRESHOLD && !stacked {
+                let old_node_count = self.node_count();
+                self.rebalance();
+                return InsertResult::Rebalanced { depth, old_node_count, new_node_count: self.node_count() }
+            }
+            return InsertResult::Inserted { depth }
+        }
+
+        if !self.divided {
+            self.subdivide();
+        }
+        for child in [&mut self.top_left, &mut self.top_right, &mut self.bottom_left, &mut self.bottom_right] {
+            let child = child.as_mut().unwrap();
+            if child.boundary.contains_point(point) {
+                let result = child.adaptive_insert_at(point, depth + 1);
+                if !matches!(result, InsertResult::Rejected { .. }) {
+                    self.count += 1;
+                }
+                return result
+            }
+        }
+        InsertResult::Rejected { reason: InsertRejection::OutOfBounds }
+    }
+
+    /// Build a quadtree from a batch of points top down with Sort-Tile-Recursive splits instead of
+    /// inserting them one at a time. a node that can't keep its points sorts them by x and cuts them
+    /// into two slabs at the median, sorts each slab by y and cuts it at its median, and the four
+    /// tiles become its children, so cells follow the data rather than the boundary's center and
+    /// every leaf ends up about equally full. points land in the first child whose cell holds them,
+    /// the same rule insert uses, so later inserts and removes find them. nodes stop splitting
+    /// BULK_LOAD_MAX_DEPTH levels down. points outside boundary are dropped
+    pub fn bulk_load(points: Vec<Point<T>>, boundary: Qrect, capacity: usize) -> Quadtree<T> {
+        let mut qt = Quadtree::new(boundary, capacity);
+        let points = points.into_iter().filter(|point| qt.boundary.contains_point(point)).collect();
+        qt.bulk_load_node(points, 0);
+        qt
+    }
+
+    fn bulk_load_node(&mut self, points: Vec<Point<T>>, depth: usize) {
+        if let Some([top_left, top_right, bottom_left, bottom_right]) = self.load_leaf_or_split(points, depth) {
+            self.top_left.as_mut().unwrap().bulk_load_node(top_left, depth + 1);
+            self.top_right.as_mut().unwrap().bulk_load_node(top_right, depth + 1);
+            self.bottom_left.as_mut().unwrap().bulk_load_node(bottom_left, depth + 1);
+            self.bottom_right.as_mut().unwrap().bulk_load_node(bottom_right, depth + 1);
+        }
+    }
+
+    // keep the points here if they fit, otherwise split into STR tiles and hand back what each child should get
+    fn load_leaf_or_split(&mut self, mut points: Vec<Point<T>>, depth: usize) -> Option<[Vec<Point<T>>; 4]> {
+        self.count = points.len();
+        // stacks of points on one spot can't be split so they stay together
+        let stacked = points.iter().all(|p| p.x == points[0].x && p.y == points[0].y);
+        if points.len() <= self.capacity || stacked || depth >= BULK_LOAD_MAX_DEPTH {
+            self.points = points;
+            self.generation = next_generation();
+            return None
+        }
+
+        let b = self.boundary.clone();
+        points.sort_by(|a, b| a.x.total_cmp(&b.x));
+        let mid = points.len() / 2;
+        let cut_x = median_cut(points.iter().map(|p| p.x), mid, b.x);
+        points[..mid].sort_by(|a, b| a.y.total_cmp(&b.y));
+        points[mid..].sort_by(|a, b| a.y.total_cmp(&b.y));
+        let cut_left = median_cut(points[..mid].iter().map(|p| p.y), mid / 2, b.y);
+        let cut_right = median_cut(points[mid..].iter().map(|p| p.y), (points.len() - mid) / 2, b.y);
+        self.subdivide_into([
+            Qrect::from_min_max(b.min_x(), b.min_y(), cut_x, cut_left),
+            Qrect::from_min_max(cut_x, b.min_y(), b.max_x(), cut_right),
+            Qrect::from_min_max(b.min_x(), cut_left, cut_x, b.max_y()),
+            Qrect::from_min_max(cut_x, cut_right, b.max_x(), b.max_y()),
+        ]);
+
+        // a cut that lands on shared coordinates can send a point to the other slab's tile,
+        // and one that rounding leaves outside every tile stays here
+        let mut tiles: [Vec<Point<T>>; 4] = Default::default();
+        for point in points {
+            match Quadrant::ALL.iter().position(|&quadrant| self.child(quadrant).unwrap().boundary.contains_point(&point)) {
+                Some(i) => tiles[i].push(point),
+                None => self.points.push(point),
+            }
+        }
+        Some(tiles)
+    }
+
+    /// Check the tree is well formed, see structural_check, and that every point sits inside its node
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.structural_check() {
+            return Err("node layout is inconsistent".to_string())
+        }
+
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if let Some(point) = node.points.iter().find(|point| !node.boundary.contains_point(point)) {
+                return Err(format!("point at ({}, {}) is outside its node", point.x, point.y))
+            }
+            stack.extend(node.children());
+        }
+        Ok(())
+    }
+
+    /// Query the quadtree for points within a rectangle ordered from center outward in bands,
+    /// each an eighth of the range's larger half size wide. points within a band keep query_rect order
+    pub fn query_rect_spiral(&self, range: &Qrect, center: (f32, f32)) -> Vec<Point<T>> {
+        let band_width = range.w.max(range.h) / 8.;
+        let band = |point: &Point<T>| {
+            let dist = ((point.x - center.0).powi(2) + (point.y - center.1).powi(2)).sqrt();
+            if band_width > 0. { (dist / band_width) as usize } else { 0 }
+        };
+
+        let mut found = self.query_rect(range);
+        found.sort_by_key(|point| band(point));
+        found
+    }
+
+    /// Merge another quadtree's points into this one. when both share a boundary and capacity the
+    /// nodes are merged pairwise and only nodes left over capacity push points down, otherwise
+    /// every point is inserted one by one. a tree with quantization or auto rebalance also goes
+    /// point by point so the merged points are snapped and rebalanced like any insert
+    pub fn merge_aligned(&mut self, other: &Quadtree<T>) {
+        if self.boundary != other.boundary || self.capacity != other.capacity || self.quantum().is_some() || self.auto_rebalance_threshold().is_some() {
+            for point in other.iter_rect(&other.boundary) {
+                self.insert(point);
+            }
+            return
+        }
+        self.merge_node(other);
+    }
+
+    fn merge_node(&mut self, other: &Quadtree<T>) {
+        self.points.extend(other.points.iter().cloned());
+        self.generation = next_generation();
+
+        // a bulk loaded node can be split off center, then the cells don't pair up
+        let same_cells = if self.divided {
+            self.children().map(|child| &child.boundary).eq(other.children().map(|child| &child.boundary))
+        } else {
+            other.split_at_center()
+        };
+        if other.divided && !same_cells {
+            self.points.extend(other.children().flat_map(|child| child.iter_rect(&child.boundary)).cloned());
+        } else if other.divided {
+            if !self.divided {
+                self.subdivide();
+            }
+            self.top_left.as_mut().unwrap().merge_node(other.top_left.as_ref().unwrap());
+            self.top_right.as_mut().unwrap().merge_node(other.top_right.as_ref().unwrap());
+            self.bottom_left.as_mut().unwrap().merge_node(other.bottom_left.as_ref().unwrap());
+            self.bottom_right.as_mut().unwrap().merge_node(other.bottom_right.as_ref().unwrap());
+        }
+
+        if self.points.len() > self.capacity {
+            let overflow = self.points.split_off(self.capacity);
+            for point in &overflow {
+                self.insert_node(point);
+            }
+        }
+        self.recount();
+    }
+
+    /// Query the quadtree for points within a rectangle until deadline passes, returns the points
+    /// found and whether the query finished. nodes fully inside range are visited before nodes that
+    /// only overlap it since every point they hold counts. the clock is read once per node visited,
+    /// which costs roughly as much as scanning a small node so keep the capacity reasonable
+    pub fn query_rect_bounded(&self, range: &Qrect, deadline: std::time::Instant) -> (Vec<Point<T>>, bool) {
+        let mut found = vec![];
+        if !range.is_finite() {
+            return (found, true)
+        }
+
+        let mut inside = vec![];
+        let mut partial = vec![self];
+        while let Some(node) = inside.pop().or_else(|| partial.pop()) {
+            if std::time::Instant::now() >= deadline {
+                return (found, false)
+            }
+            if !node.boundary.intersects_rect(range) {
+                continue
+            }
+
+            if range.contains_rect(&node.boundary) {
+                found.extend(node.points.iter().cloned());
+                inside.extend(node.children());
+            } else {
+                found.extend(node.points.iter().filter(|point| range.contains_point(point)).cloned());
+                for child in node.children() {
+                    if range.contains_rect(&child.boundary) {
+                        inside.push(child);
+                    } else {
+                        partial.push(child);
+                    }
+                }
+            }
+        }
+
+        (found, true)
+    }
+
+    /// How full the leaves are on average, points held by leaves / (leaf_count * capacity).
+    /// divided nodes always hold a full capacity here so only leaves are counted. near 1 the leaves
+    /// are packed, near 0 there are many empty or sparse leaves costing memory and traversal time.
+    /// 0.5 to 0.8 is typical for uniform random data with a well chosen capacity
+    pub fn occupancy_ratio(&self) -> f32 {
+        let leaves = self.leaf_count();
+        if leaves == 0 || self.capacity == 0 {
+            return 0.
+        }
+        let leaf_points: usize = self.leaves().map(|leaf| leaf.points.len()).sum();
+        leaf_points as f32 / (leaves * self.capacity) as f32
+    }
+
+    /// A fingerprint of the change stamps of every node overlapping range, it only changes when
+    /// points or nodes that could affect a query over range are changed. compare it for equality,
+    /// stamps come from per thread blocks so a bigger value doesn't mean a newer region
+    pub fn region_generation(&self, range: &Qrect) -> u64 {
+        let mut fingerprint = 0u64;
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.boundary.intersects_rect(range) {
+                fingerprint = (fingerprint.rotate_left(5) ^ node.generation).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                stack.extend(node.children());
+            }
+        }
+        fingerprint
+    }
+
+    /// Fold over every point within a rectangle without allocating
+    pub fn fold_in_rect<B>(&self, range: &Qrect, init: B, f: impl Fn(B, &Point<T>) -> B) -> B {
+        if !range.is_finite() {
+            return init
+        }
+        self.fold_filtered(range, init, &|point| range.contains_point(point), &f)
+    }
+
+    /// Fold over every point less than r away from (cx, cy) without allocating
+    pub fn fold_in_circle<B>(&self, cx: f32, cy: f32, r: f32, init: B, f: impl Fn(B, &Point<T>) -> B) -> B {
+        // same bounding rect prefilter as query_circle
+        let rect = Qrect::new(cx, cy, r, r);
+        if !rect.is_finite() {
+            return init
+        }
+        let inside = |point: &Point<T>| {
+            let dist_x = point.x - cx;
+            let dist_y = point.y - cy;
+            rect.contains_point(point) && dist_x * dist_x + dist_y * dist_y < r * r
+        };
+        self.fold_filtered(&rect, init, &inside, &f)
+    }
+
+    /// number of points less than r away from (cx, cy), counted with fold_in_circle
+    pub fn fold_in_circle_count(&self, cx: f32, cy: f32, r: f32) -> usize {
+        self.fold_in_circle(cx, cy, r, 0, |count, _| count + 1)
+    }
+
+    fn fold_filtered<B>(&self, range: &Qrect, init: B, keep: &impl Fn(&Point<T>) -> bool, f: &impl Fn(B, &Point<T>) -> B) -> B {
+        if !self.boundary.intersects_rect(range) {
+            return init
+        }
+
+        let mut acc = init;
+        for point in &self.points {
+            if keep(point) {
+                acc = f(acc, point);
+            }
+        }
+        for child in self.children() {
+            acc = child.fold_filtered(range, acc, keep, f);
+        }
+        acc
+    }
+
+    /// Insert a point tagged with a z layer
+    pub fn insert_layered(&mut self, point: &Point<T>, layer: u8) -> bool {
+        let mut point = point.clone();
+        point.layer = layer;
+        self.insert(&point)
+    }
+
+    /// Query the quadtree for points within a rectangle on one layer
+    pub fn query_rect_layer(&self, range: &Qrect, layer: u8) -> Vec<Point<T>> {
+        self.iter_rect(range).filter(|point| point.layer == layer).cloned().collect()
+    }
+
+    /// Sort points into distance bands around (cx, cy) in one pass. buckets is an ascending list of
+    /// radii and result[i] holds the points with buckets[i - 1] <= distance < buckets[i], points past
+    /// the last radius are left out so end with f32::INFINITY to keep everything
+    pub fn group_by_distance_buckets(&self, cx: f32, cy: f32, buckets: &[f32]) -> Vec<Vec<Point<T>>> {
+        let mut groups = vec![vec![]; buckets.len()];
+        if let Some(&last) = buckets.last() {
+            self.group_by_distance_into(cx, cy, buckets, last * last, &mut groups);
+        }
+        groups
+    }
+
+    fn group_by_distance_into(&self, cx: f32, cy: f32, buckets: &[f32], max_dist_sq: f32, groups: &mut Vec<Vec<Point<T>>>) {
+        if self.boundary.distance_sq_to(cx, cy) >= max_dist_sq {
+            return
+        }
+
+        for point in &self.points {
+            let dist = ((point.x - cx).powi(2) + (point.y - cy).powi(2)).sqrt();
+            let i = buckets.partition_point(|&radius| radius <= dist);
+            if i < buckets.len() {
+                groups[i].push(point.clone());
+            }
+        }
+        for child in self.children() {
+            child.group_by_distance_into(cx, cy, buckets, max_dist_sq, groups);
+        }
+    }
+
+    /// Query the quadtree for points within a rectangle spending at most budget on it,
+    /// returns the points found and whether the query finished, see query_rect_bounded
+    pub fn query_rect_timed(&self, range: &Qrect, budget: std::time::Duration) -> (Vec<Point<T>>, bool) {
+        self.query_rect_bounded(range, std::time::Instant::now() + budget)
+    }
+
+    /// Point counts for every node laid out like the tree, counts are per node not per subtree
+    pub fn quadrant_count_tree(&self) -> QuadrantCounts {
+        let children = if self.divided {
+            Some(Box::new([
+                self.top_left.as_ref().unwrap().quadrant_count_tree(),
+                self.top_right.as_ref().unwrap().quadrant_count_tree(),
+                self.bottom_left.as_ref().unwrap().quadrant_count_tree(),
+                self.bottom_right.as_ref().unwrap().quadrant_count_tree(),
+            ]))
+        } else {
+            None
+        };
+        QuadrantCounts { count: self.points.len(), children }
+    }
+
+    /// Query the quadtree for points within a circle given its squared radius,
+    /// equivalent to query_circle(x, y, range_sq.sqrt())
+    pub fn query_circle_sq(&self, x: f32, y: f32, range_sq: f32) -> Vec<Point<T>> {
+        let range = range_sq.sqrt();
+        let mut temp = self.query_rect(Qrect::new(x, y, range, range));
+        temp.retain(|point| {
+            let dist_x = point.x - x;
+            let dist_y = point.y - y;
+            dist_x * dist_x + dist_y * dist_y < range_sq
+        });
+        temp
+    }
+
+    /// Query the quadtree for points within range that aren't within exclusion
+    pub fn query_excluding_rect(&self, range: &Qrect, exclusion: &Qrect) -> Vec<Point<T>> {
+        let mut temp = self.query_rect(range);
+        temp.retain(|point| !exclusion.contains_point(point));
+        temp
+    }
+
+    /// Query the quadtree for points within range that aren't within any of the exclusions,
+    /// subtrees entirely covered by one exclusion are skipped
+    pub fn query_rect_with_exclusions(&self, range: &Qrect, exclusions: &[Qrect]) -> Vec<Point<T>> {
+        let mut found = vec![];
+        if range.is_finite() {
+            self.query_rect_with_exclusions_into(range, exclusions, &mut found);
+        }
+        found
+    }
+
+    fn query_rect_with_exclusions_into(&self, range: &Qrect, exclusions: &[Qrect], found: &mut Vec<Point<T>>) {
+        if !self.boundary.intersects_rect(range) || exclusions.iter().any(|e| e.contains_rect(&self.boundary)) {
+            return
+        }
+
+        for point in &self.points {
+            if range.contains_point(point) && !exclusions.iter().any(|e| e.contains_point(point)) {
+                found.push(point.clone());
+            }
+        }
+        for child in self.children() {
+            child.query_rect_with_exclusions_into(range, exclusions, found);
+        }
+    }
+
+    /// Query the quadtree for references to points within a rectangle paired with the depth of
+    /// the node holding them, deeper points sit in denser parts of the tree
+    pub fn query_rect_with_depth(&self, range: &Qrect) -> Vec<(&Point<T>, usize)> {
+        let mut found = vec![];
+        if range.is_finite() {
+            self.query_rect_with_depth_into(range, 0, &mut found);
+        }
+        found
+    }
+
+    fn query_rect_with_depth_into<'a>(&'a self, range: &Qrect, depth: usize, found: &mut Vec<(&'a Point<T>, usize)>) {
+        if !self.boundary.intersects_rect(range) {
+            return
+        }
+
+        for point in &self.points {
+            if range.contains_point(point) {
+                found.push((point, depth));
+            }
+        }
+        for child in self.children() {
+            child.query_rect_with_depth_into(range, depth + 1, found);
+        }
+    }
+
+    /// Lazily iterate over references to every point
+    pub fn iter(&self) -> RectIter<'_, T> {
+        self.iter_rect(&self.boundary)
+    }
+
+    /// true if every point passes predicate, stops at the first failure. true for an empty quadtree
+    pub fn all_points_satisfy(&self, predicate: impl Fn(&Point<T>) -> bool) -> bool {
+        self.iter().all(predicate)
+    }
+
+    /// true if no point passes predicate, stops at the first match. true for an empty quadtree
+    pub fn none_satisfy(&self, predicate: impl Fn(&Point<T>) -> bool) -> bool {
+        !self.iter().any(predicate)
+    }
+
+    /// number of points passing predicate
+    pub fn count_satisfying(&self, predicate: impl Fn(&Point<T>) -> bool) -> usize {
+        self.iter().filter(|point| predicate(point)).count()
+    }
+
+    /// Distance from a coordinate to the nearest edge of the root boundary, negative when the coordinate is outside
+    pub fn distance_to_boundary(&self, x: f32, y: f32) -> f32 {
+        let b = &self.boundary;
+        let outside_sq = b.distance_sq_to(x, y);
+        if outside_sq > 0. {
+            return -outside_sq.sqrt()
+        }
+
+        let dx = (x - (b.x - b.w)).min((b.x + b.w) - x);
+        let dy = (y - (b.y - b.h)).min((b.y + b.h) - y);
+        dx.min(dy)
+    }
+
+    fn unique_positions_into(&self, seen: &mut HashSet<(u32, u32)>) {
+        for point in &self.points {
+            seen.insert((point.x.to_bits(), point.y.to_bits()));
+        }
+        for child in self.children() {
+            child.unique_positions_into(seen);
+        }
+    }
+
+    /// Every distinct (x, y) in the tree compared bitwise, in no particular order
+    pub fn collect_unique_positions(&self) -> Vec<(f32, f32)> {
+        let mut seen = HashSet::new();
+        self.unique_positions_into(&mut seen);
+        seen.into_iter().map(|(x, y)| (f32::from_bits(x), f32::from_bits(y))).collect()
+    }
+
+    /// Number of distinct (x, y) in the tree compared bitwise, without building the position list
+    pub fn count_unique_positions(&self) -> usize {
+        let mut seen = HashSet::new();
+        self.unique_positions_into(&mut seen);
+        seen.len()
+    }
+
+    /// Query the quadtree for points within a box of half extents (half_w, half_h) centred on (cx, cy) and rotated by angle_rad
+    pub fn query_obb(&self, cx: f32, cy: f32, half_w: f32, half_h: f32, angle_rad: f32) -> Vec<Point<T>> {
+        self.query_in_rotated_frame(cx, cy, angle_rad, &Qrect::new(cx, cy, half_w, half_h))
+    }
+
+    /// Collect all points as ([x, y], data) with the coordinates widened to f64
+    pub fn collect_as_f64_coords(&self) -> Vec<([f64; 2], T)> {
+        self.iter()
+            .map(|point| ([point.x as f64, point.y as f64], point.data.clone()))
+            .collect()
+    }
+
+    /// Insert a point given f64 coordinates, they are narrowed to f32 first
+    pub fn insert_from_f64_coords(&mut self, x: f64, y: f64, data: T) -> bool {
+        self.insert(&Point::new(x as f32, y as f32, data))
+    }
+
+    /// Query the quadtree for points within a circle given in f64, narrowed to f32 first
+    pub fn query_circle_f64(&self, cx: f64, cy: f64, r: f64) -> Vec<Point<T>> {
+        self.query_circle(cx as f32, cy as f32, r as f32)
+    }
+
+    // recursion instead of a stack keeps this free of heap allocation
+    fn query_rect_slots_into(&self, range: &Qrect, out: &mut [Option<Point<T>>], count: &mut usize) {
+        if *count == out.len() || !self.boundary.intersects_rect(range) {
+            return
+        }
+
+        for point in &self.points {
+            if range.contains_point(point) {
+                out[*count] = Some(point.clone());
+                *count += 1;
+                if *count == out.len() {
+                    return
+                }
+            }
+        }
+        for child in self.children() {
+            child.query_rect_slots_into(range, out, count);
+        }
+    }
+
+    /// Query the quadtree for up to N points within a rectangle into a fixed size array without allocating,
+    /// returns the array and how many slots were filled
+    pub fn query_rect_array<const N: usize>(&self, range: &Qrect) -> ([Option<Point<T>>; N], usize) {
+        let mut out = std::array::from_fn(|_| None);
+        let mut count = 0;
+        if range.is_finite() {
+            self.query_rect_slots_into(range, &mut out, &mut count);
+        }
+        (out, count)
+    }
+
+    /// Build a quadtree from arbitrary items, coord reads the position out of each item and the item becomes the data.
+    /// items outside boundary are dropped like with insert
+    pub fn from_items(boundary: Qrect, capacity: usize, items: impl IntoIterator<Item = T>, coord: impl Fn(&T) -> (f32, f32)) -> Self {
+        let mut qt = Quadtree::new(boundary, capacity);
+        for item in items {
+            let (x, y) = coord(&item);
+            qt.insert(&Point::new(x, y, item));
+        }
+        qt
+    }
+
+    /// Gaussian kernel density estimate at (x, y), each point weighs exp(-0.5 * (d / radius)^2)
+    /// and the sum is spread over the area of a circle of radius. 0 when nothing is near
+    pub fn local_density(&self, x: f32, y: f32, radius: f32) -> f32 {
+        // three standard deviations out holds 99.7% of the weight
+        let total: f32 = self.query_circle(x, y, 3. * radius).iter()
+            .map(|point| {
+                let d = ((point.x - x).powi(2) + (point.y - y).powi(2)).sqrt();
+                (-0.5 * (d / radius).powi(2)).exp()
+            })
+            .sum();
+        total / (std::f32::consts::PI * radius * radius)
+    }
+
+    /// Points per unit area over the bounding box of every point, 0 for an empty tree.
+    /// infinite when the points all sit on one line
+    pub fn uniform_density(&self) -> f32 {
+        match self.bounding_box() {
+            Some(bounds) => self.len() as f32 / (bounds.w * 2. * bounds.h * 2.),
+            None => 0.,
+        }
+    }
+
+    /// Query the quadtree for points in the cells set to true of a cols by rows mask laid over the root boundary,
+    /// row major from the top left. panics if mask isn't cols * rows long
+    pub fn query_mask(&self, mask: &[bool], cols: usize, rows: usize) -> Vec<Point<T>> {
+        assert_eq!(mask.len(), cols * rows, "mask must hold cols * rows cells");
+
+        let b = &self.boundary;
+        let cell_w = b.w * 2. / cols as f32;
+        let cell_h = b.h * 2. / rows as f32;
+        self.iter()
+            .filter(|point| {
+                // points on the far edges belong to the last column and row
+                let col = (((point.x - b.min_x()) / cell_w) as usize).min(cols - 1);
+                let row = (((point.y - b.min_y()) / cell_h) as usize).min(rows - 1);
+                mask[row * cols + col]
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// How many leaves hold each number of points, keyed by points per leaf.
+    /// points kept by divided nodes aren't part of any leaf and aren't counted
+    pub fn leaf_point_distribution(&self) -> BTreeMap<usize, usize> {
+        let mut distribution = BTreeMap::new();
+        for leaf in self.leaves() {
+            *distribution.entry(leaf.points.len()).or_insert(0) += 1;
+        }
+        distribution
+    }
+
+    /// Cumulative form of leaf_point_distribution, the fraction of leaves holding at most each point count
+    pub fn leaf_utilization_cdf(&self) -> Vec<(usize, f32)> {
+        let distribution = self.leaf_point_distribution();
+        let leaves: usize = distribution.values().sum();
+
+        let mut running = 0;
+        distribution.into_iter()
+            .map(|(count, freq)| {
+                running += freq;
+                (count, running as f32 / leaves as f32)
+            })
+            .collect()
+    }
+
+    /// Approximate nearest point to (x, y), found by walking straight down to the leaf under (x, y)
+    /// and only looking at the nodes on the way plus that leaf's siblings. fast but may miss a closer
+    /// point just across a cell edge, use nearest_where when the answer has to be exact.
+    /// falls back to the exact search when the walk finds nothing, so it's only None for an empty tree
+    pub fn approx_nearest(&self, x: f32, y: f32) -> Option<Point<T>> {
+        let mut visited: Vec<&[Point<T>]> = vec![&self.points];
+        let mut node = self;
+        while node.divided {
+            let next = node.children()
+                .min_by(|a, b| a.boundary.distance_sq_to(x, y).total_cmp(&b.boundary.distance_sq_to(x, y)))
+                .unwrap();
+            if next.divided {
+                visited.push(&next.points);
+                node = next;
+            } else {
+                visited.extend(node.children().map(|child| child.points.as_slice()));
+                break
+            }
+        }
+
+        let best = visited.into_iter()
+            .flatten()
+            .map(|point| (point, (point.x - x).powi(2) + (point.y - y).powi(2)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        match best {
+            Some((point, _)) => Some(point.clone()),
+            None => self.nearest_where(x, y, |_| true),
+        }
+    }
+
+    /// Points with no other point less than min_distance away
+    pub fn find_isolated_points(&self, min_distance: f32) -> Vec<&Point<T>> {
+        // the point always counts itself
+        self.iter()
+            .filter(|point| self.fold_in_circle_count(point.x, point.y, min_distance) <= 1)
+            .collect()
+    }
+
+    /// the smallest axis aligned rect containing every point, same as bounding_box
+    pub fn min_enclosing_rect(&self) -> Option<Qrect> {
+        self.bounding_box()
+    }
+
+    /// true if the boundary is exactly the smallest rect around the points, false for an empty quadtree
+    pub fn is_boundary_tight(&self) -> bool {
+        self.min_enclosing_rect().as_ref() == Some(&self.boundary)
+    }
+
+    /// Shrink the boundary to min_enclosing_rect and rebuild the nodes inside it,
+    /// an empty quadtree keeps its boundary
+    pub fn tighten_boundary(&mut self) {
+        let Some(tight) = self.min_enclosing_rect() else { return };
+        if tight == self.boundary {
+            return
+        }
+
+        let points = self.collect();
+        self.empty();
+        self.boundary = tight;
+        for point in &points {
+            self.insert_node(point);
+        }
+    }
+
+    /// Rough heap and inline size of the tree in bytes, nodes plus the full capacity of every
+    /// point vec. anything T owns on the heap isn't counted
+    pub fn approximate_memory_bytes(&self) -> usize {
+        std::mem::size_of::<Quadtree<T>>()
+            + self.points.capacity() * std::mem::size_of::<Point<T>>()
+            + self.children().map(|child| child.approximate_memory_bytes()).sum::<usize>()
+    }
+
+    /// Bytes spent on the tree itself, the nodes and the unused slack in their point vecs
+    pub fn structural_memory_bytes(&self) -> usize {
+        std::mem::size_of::<Quadtree<T>>()
+            + (self.points.capacity() - self.points.len()) * std::mem::size_of::<Point<T>>()
+            + self.children().map(|child| child.structural_memory_bytes()).sum::<usize>()
+    }
+
+    /// Bytes taken by the stored points themselves
+    pub fn data_memory_bytes(&self) -> usize {
+        self.len() * std::mem::size_of::<Point<T>>()
+    }
+
+    /// structural_memory_bytes over data_memory_bytes, a high ratio means a flat list may be the better fit.
+    /// infinite for an empty quadtree
+    pub fn memory_overhead_ratio(&self) -> f32 {
+        self.structural_memory_bytes() as f32 / self.data_memory_bytes() as f32
+    }
+
+    /// Query the quadtree for points within a rectangle scanning at most max_nodes nodes that overlap it,
+    /// returns the points found and whether the query finished
+    pub fn query_rect_node_budget(&self, range: &Qrect, max_nodes: usize) -> (Vec<Point<T>>, bool) {
+        let mut found = vec![];
+        if !range.is_finite() {
+            return (found, true)
+        }
+
+        let mut visited = 0;
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if !node.boundary.intersects_rect(range) {
+                continue
+            }
+            if visited == max_nodes {
+                return (found, false)
+            }
+            visited += 1;
+
+            found.extend(node.points.iter().filter(|point| range.contains_point(point)).cloned());
+            stack.extend(node.children());
+        }
+        (found, true)
+    }
+
+    /// Every unordered pair of points less than radius apart, each pair shows up once.
+    /// the tree is joined with itself so a node's points are only compared with nodes near enough to hold a partner
+    pub fn pairs_within(&self, radius: f32) -> Vec<(Point<T>, Point<T>)> {
+        let mut pairs = vec![];
+        self.for_each_pair_within(0, radius * radius, &mut |(_, a), (_, b)| pairs.push((a.clone(), b.clone())));
+        pairs
+    }
+
+    // calls f once per unordered pair closer than sqrt(r_sq), each point comes with its position in iter order.
+    // base is where self's own points start in that order, its children follow them one after another.
+    // pairs inside one node are taken in index order, pairs between a node and what's below it or between
+    // two sibling subtrees are handed to pairs_with_points and cross_pairs, which never look at the same two subtrees twice
+    fn for_each_pair_within<'a>(&'a self, base: usize, r_sq: f32, f: &mut impl FnMut((usize, &'a Point<T>), (usize, &'a Point<T>))) {
+        for (i, a) in self.points.iter().enumerate() {
+            for (j, b) in self.points.iter().enumerate().skip(i + 1) {
+                if (a.x - b.x).powi(2) + (a.y - b.y).powi(2) < r_sq {
+                    f((base + i, a), (base + j, b));
+                }
+            }
+        }
+
+        let children = self.children_with_bases(base);
+        for (i, &(child, child_base)) in children.iter().enumerate() {
+            child.pairs_with_points(&self.points, base, child_base, r_sq, f);
+            child.for_each_pair_within(child_base, r_sq, f);
+            for &(other, other_base) in &children[i + 1..] {
+                child.cross_pairs(child_base, other, other_base, r_sq, f);
+            }
+        }
+    }
+
+    // each child next to the index its first point has in iter order, given where self's points start
+    fn children_with_bases(&self, base: usize) -> Vec<(&Quadtree<T>, usize)> {
+        let mut next = base + self.points.len();
+        self.children()
+            .map(|child| {
+                let child_base = next;
+                next += child.count;
+                (child, child_base)
+            })
+            .collect()
+    }
+
+    // pairs between a point anywhere under self and a point anywhere under other, the two don't overlap
+    fn cross_pairs<'a>(&'a self, base: usize, other: &'a Quadtree<T>, other_base: usize, r_sq: f32, f: &mut impl FnMut((usize, &'a Point<T>), (usize, &'a Point<T>))) {
+        if self.boundary.gap_sq_to(&other.boundary) >= r_sq {
+            return
+        }
+        other.pairs_with_points(&self.points, base, other_base, r_sq, f);
+        for (child, child_base) in self.children_with_bases(base) {
+            child.cross_pairs(child_base, other, other_base, r_sq, f);
+        }
+    }
+
+    // pairs between one of points, numbered from points_base, and a point anywhere under self
+    fn pairs_with_points<'a>(&'a self, points: &'a [Point<T>], points_base: usize, base: usize, r_sq: f32, f: &mut impl FnMut((usize, &'a Point<T>), (usize, &'a Point<T>))) {
+        let Some(first) = points.first() else { return };
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (first.x, first.y, first.x, first.y);
+        for point in points {
+            (min_x, min_y) = (min_x.min(point.x), min_y.min(point.y));
+            (max_x, max_y) = (max_x.max(point.x), max_y.max(point.y));
+        }
+        self.pairs_with_points_in(points, points_base, &Qrect::from_min_max(min_x, min_y, max_x, max_y), base, r_sq, f);
+    }
+
+    fn pairs_with_points_in<'a>(&'a self, points: &'a [Point<T>], points_base: usize, bounds: &Qrect, base: usize, r_sq: f32, f: &mut impl FnMut((usize, &'a Point<T>), (usize, &'a Point<T>))) {
+        if self.boundary.gap_sq_to(bounds) >= r_sq {
+            return
+        }
+        for (i, a) in points.iter().enumerate() {
+            for (j, b) in self.points.iter().enumerate() {
+                if (a.x - b.x).powi(2) + (a.y - b.y).powi(2) < r_sq {
+                    f((points_base + i, a), (base + j, b));
+                }
+            }
+        }
+        for (child, child_base) in self.children_with_bases(base) {
+            child.pairs_with_points_in(points, points_base, bounds, child_base, r_sq, f);
+        }
+    }
+
+    /// Every point paired with all the other points less than radius from it, in iter order.
+    /// built from the same pair join as pairs_within, each pair is pushed onto both of its points' lists
+    pub fn to_adjacency_list(&self, radius: f32) -> Vec<(Point<T>, Vec<Point<T>>)> {
+        let mut lists: Vec<(Point<T>, Vec<Point<T>>)> = self.iter().map(|point| (point.clone(), vec![])).collect();
+        self.for_each_pair_within(0, radius * radius, &mut |(i, a), (j, b)| {
+            lists[i].1.push(b.clone());
+            lists[j].1.push(a.clone());
+        });
+        lists
+    }
+
+    /// The number of unordered pairs less than radius apart, pairs_within(radius).len() without building the pairs
+    pub fn edge_count(&self, radius: f32) -> usize {
+        let mut edges = 0;
+        self.for_each_pair_within(0, radius * radius, &mut |_, _| edges += 1);
+        edges
+    }
+
+    /// Query the quadtree for points within the world space viewport and pair each with its
+    /// position after world_to_screen
+    pub fn query_viewport(&self, viewport: &Qrect, world_to_screen: impl Fn(f32, f32) -> (f32, f32)) -> Vec<(Point<T>, (f32, f32))> {
+        self.iter_rect(viewport)
+            .map(|point| (point.clone(), world_to_screen(point.x, point.y)))
+            .collect()
+    }
+
+    /// Mutable references to the data of every point within range, positions can't change so the tree stays valid
+    pub fn region_data_mut(&mut self, range: &Qrect) -> Vec<&mut T> {
+        let mut found = vec![];
+        if range.is_finite() {
+            self.region_data_mut_into(range, &mut found);
+        }
+        found
+    }
+
+    fn region_data_mut_into<'a>(&'a mut self, range: &Qrect, found: &mut Vec<&'a mut T>) {
+        if !self.boundary.intersects_rect(range) {
+            return
+        }
+
+        // the data may change so cached results over this node are no longer good
+        if self.points.iter().any(|point| range.contains_point(point)) {
+            self.generation = next_generation();
+        }
+        for point in self.points.iter_mut() {
+            if range.contains_point(point) {
+                found.push(&mut point.data);
+            }
+        }
+        for child in [&mut self.top_left, &mut self.top_right, &mut self.bottom_left, &mut self.bottom_right].into_iter().flatten() {
+            child.region_data_mut_into(range, found);
+        }
+    }
+
+    /// number of points within range, nodes entirely inside range add their cached count without being walked
+    pub fn count_in_rect(&self, range: &Qrect) -> usize {
+        if !range.is_finite() || !self.boundary.intersects_rect(range) {
+            return 0
+        }
+        if range.contains_rect(&self.boundary) {
+            return self.count
+        }
+
+        self.points.iter().filter(|point| range.contains_point(point)).count()
+            + self.children().map(|child| child.count_in_rect(range)).sum::<usize>()
+    }
+
+    // the field holding the child in quadrant
+    fn child_slot(&mut self, quadrant: Quadrant) -> &mut Option<Box<Quadtree<T>>> {
+        match quadrant {
+            Quadrant::TopLeft => &mut self.top_left,
+            Quadrant::TopRight => &mut self.top_right,
+            Quadrant::BottomLeft => &mut self.bottom_left,
+            Quadrant::BottomRight => &mut self.bottom_right,
+        }
+    }
+
+    /// the child in quadrant, None for an undivided node
+    pub fn child(&self, quadrant: Quadrant) -> Option<&Quadtree<T>> {
+        match quadrant {
+            Quadrant::TopLeft => self.top_left.as_deref(),
+            Quadrant::TopRight => self.top_right.as_deref(),
+            Quadrant::BottomLeft => self.bottom_left.as_deref(),
+            Quadrant::BottomRight => self.bottom_right.as_deref(),
+        }
+    }
+
+    /// The node reached by following path down from this one, None if it leaves the tree
+    pub fn node_at_path(&self, path: &[Quadrant]) -> Option<&Quadtree<T>> {
+        let mut node = self;
+        for &quadrant in path {
+            node = node.child(quadrant)?;
+        }
+        Some(node)
+    }
+
+    /// Insert a point like insert and report the path to the node that took it, None if it was rejected.
+    /// meant for debugging how the tree subdivides
+    pub fn insert_with_path(&mut self, point: &Point<T>) -> Option<NodePath> {
+        let point = self.prepare_point(point);
+
+        let mut path = vec![];
+        if self.insert_node_with_path(&point, &mut path, self.auto_rebalance_threshold().is_some()) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    // insert_node that records the quadrants it walks through. with keep_stacks a full node
+    // whose points all share the new point's position takes it instead of passing it down
+    fn insert_node_with_path(&mut self, point: &Point<T>, path: &mut NodePath, keep_stacks: bool) -> bool {
+        if !self.boundary.contains_point(point) {
+            return false
+        }
+
+        let stacked = keep_stacks && !self.points.is_empty() && self.points.iter().all(|p| p.x == point.x && p.y == point.y);
+        if self.points.len() < self.capacity || stacked {
+            self.points.push(point.clone());
+            self.generation = next_generation();
+            self.count += 1;
+            return true
+        }
+
+        if !self.divided {
+            self.subdivide();
+        }
+        for quadrant in Quadrant::ALL {
+            path.push(quadrant);
+            if self.child_slot(quadrant).as_mut().unwrap().insert_node_with_path(point, path, keep_stacks) {
+                self.count += 1;
+                return true
+            }
+            path.pop();
+        }
+        false
+    }
+
+    /// Detach the subtree at path and return it, None for an empty path or one that leaves the tree.
+    /// an empty node takes its place so the parent keeps four children, and a parent left with only
+    /// empty children is collapsed back to undivided
+    pub fn delete_subtree_at(&mut self, path: &[Quadrant]) -> Option<Quadtree<T>> {
+        let (&quadrant, rest) = path.split_first()?;
+        if !self.divided {
+            return None
+        }
+
+        let slot = self.child_slot(quadrant);
+        let detached = if rest.is_empty() {
+            let child = slot.as_ref().unwrap();
+            let placeholder = Box::new(Quadtree::new(child.boundary.clone(), child.capacity));
+            *slot.replace(placeholder).unwrap()
+        } else {
+            slot.as_mut().unwrap().delete_subtree_at(rest)?
+        };
+
+        self.count -= detached.count;
+        self.generation = next_generation();
+        self.collapse_empty_children();
+        Some(detached)
+    }
+
+    /// Put a subtree back at path, subdividing along the way as needed. fails if the path is empty,
+    /// the node there isn't empty or the subtree's boundary doesn't match that node's
+    pub fn attach_subtree_at(&mut self, path: &[Quadrant], subtree: Quadtree<T>) -> bool {
+        let Some((&quadrant, rest)) = path.split_first() else { return false };
+        if !self.divided {
+            self.subdivide();
+        }
+
+        let slot = self.child_slot(quadrant);
+        let child = slot.as_mut().unwrap();
+        let count = subtree.count;
+        let attached = if rest.is_empty() {
+            if child.count > 0 || child.divided || child.boundary != subtree.boundary {
+                false
+            } else {
+                *slot = Some(Box::new(subtree));
+                true
+            }
+        } else {
+            child.attach_subtree_at(rest, subtree)
+        };
+
+        if attached {
+            self.count += count;
+            self.generation = next_generation();
+        } else {
+            // undo any subdividing done on the way down for nothing
+            self.collapse_empty_children();
+        }
+        attached
+    }
+
+    /// Call f on every point within range without collecting them
+    pub fn for_each_in_rect(&self, range: &Qrect, mut f: impl FnMut(&Point<T>)) {
+        let _ = self.try_for_each_in_rect(range, |point| {
+            f(point);
+            std::ops::ControlFlow::<()>::Continue(())
+        });
+    }
+
+    /// Call f on every point within range until it returns Break, which is handed back.
+    /// nothing is allocated so it suits find like searches and fallible passes
+    pub fn try_for_each_in_rect<E, F: FnMut(&Point<T>) -> std::ops::ControlFlow<E>>(&self, range: &Qrect, mut f: F) -> std::ops::ControlFlow<E> {
+        if !range.is_finite() {
+            return std::ops::ControlFlow::Continue(())
+        }
+        self.try_for_each_in_rect_inner(range, &mut f)
+    }
+
+    fn try_for_each_in_rect_inner<E, F: FnMut(&Point<T>) -> std::ops::ControlFlow<E>>(&self, range: &Qrect, f: &mut F) -> std::ops::ControlFlow<E> {
+        if !self.boundary.intersects_rect(range) {
+            return std::ops::ControlFlow::Continue(())
+        }
+
+        for point in &self.points {
+            if range.contains_point(point) {
+                f(point)?;
+            }
+        }
+        for child in self.children() {
+            child.try_for_each_in_rect_inner(range, f)?;
+        }
+        std::ops::ControlFlow::Continue(())
+    }
+
+    /// Mean position of the points within range, None if there are none
+    pub fn centroid_of_rect(&self, range: &Qrect) -> Option<(f32, f32)> {
+        let (sum_x, sum_y, n) = self.fold_in_rect(range, (0., 0., 0usize), |(sx, sy, n), point| (sx + point.x, sy + point.y, n + 1));
+        if n == 0 {
+            return None
+        }
+        Some((sum_x / n as f32, sum_y / n as f32))
+    }
+
+    /// Mean position of the points less than r from (cx, cy), None if there are none
+    pub fn centroid_of_circle(&self, cx: f32, cy: f32, r: f32) -> Option<(f32, f32)> {
+        let (sum_x, sum_y, n) = self.fold_in_circle(cx, cy, r, (0., 0., 0usize), |(sx, sy, n), point| (sx + point.x, sy + point.y, n + 1));
+        if n == 0 {
+            return None
+        }
+        Some((sum_x / n as f32, sum_y / n as f32))
+    }
+
+    /// Position of the points within range averaged by weight_fn of their data,
+    /// None if there are none or the weights sum to 0
+    pub fn centroid_weighted_in_rect(&self, range: &Qrect, weight_fn: impl Fn(&T) -> f32) -> Option<(f32, f32)> {
+        let (sum_x, sum_y, total) = self.fold_in_rect(range, (0., 0., 0.), |(sx, sy, total), point| {
+            let w = weight_fn(&point.data);
+            (sx + point.x * w, sy + point.y * w, total + w)
+        });
+        if total == 0. {
+            return None
+        }
+        Some((sum_x / total, sum_y / total))
+    }
+
+    /// A new quadtree over boundary holding the points of both self and other, neither is changed.
+    /// points outside boundary are dropped
+    pub fn merged(&self, other: &Quadtree<T>, boundary: Qrect, capacity: usize) -> Quadtree<T> {
+        let mut qt = Quadtree::new(boundary, capacity);
+        for point in self.iter().chain(other.iter()) {
+            qt.insert(point);
+        }
+        qt
+    }
+
+    /// Insert every point of other, a point landing on the exact coordinates of a stored one
+    /// replaces that point's data with resolve(stored, incoming) instead of being added beside it.
+    /// returns how many points of other were left out for falling outside this boundary
+    pub fn merge_with<F: Fn(&T, &T) -> T>(&mut self, other: &Quadtree<T>, resolve: F) -> usize {
+        let mut rejected = 0;
+        for point in other.iter() {
+            match self.point_at_mut(point.x, point.y) {
+                Some(stored) => stored.data = resolve(&stored.data, &point.data),
+                None => {
+                    if !self.insert(point) {
+                        rejected += 1;
+                    }
+                }
+            }
+        }
+        rejected
+    }
+
+    // the first point stored at exactly (x, y), only nodes whose boundary holds the spot are searched
+    fn point_at_mut(&mut self, x: f32, y: f32) -> Option<&mut Point<T>> {
+        if !self.boundary.contains_point(&Point::new(x, y, ())) {
+            return None
+        }
+        if let Some(i) = self.points.iter().position(|point| point.x == x && point.y == y) {
+            // the data may change so cached results over this node are no longer good
+            self.generation = next_generation();
+            return Some(&mut self.points[i])
+        }
+        [&mut self.top_left, &mut self.top_right, &mut self.bottom_left, &mut self.bottom_right]
+            .into_iter()
+            .flatten()
+            .find_map(|child| child.point_at_mut(x, y))
+    }
+
+    /// Mean position of every point, None for an empty quadtree
+    pub fn centroid(&self) -> Option<(f32, f32)> {
+        self.centroid_of_rect(&self.boundary)
+    }
+
+    /// Insert a point carrying data at the centroid of every point, false for an empty quadtree
+    pub fn insert_at_centroid(&mut self, data: T) -> bool {
+        match self.centroid() {
+            Some((x, y)) => self.insert(&Point::new(x, y, data)),
+            None => false,
+        }
+    }
+
+    /// Insert a point carrying data at the centroid of the points within range, false if there are none
+    pub fn insert_at_centroid_of_rect(&mut self, range: &Qrect, data: T) -> bool {
+        match self.centroid_of_rect(range) {
+            Some((x, y)) => self.insert(&Point::new(x, y, data)),
+            None => false,
+        }
+    }
+
+    /// The node hierarchy as a Graphviz DOT digraph, each node labeled with its boundary and
+    /// the points it holds directly, with an edge from every parent to each of its children
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph quadtree {\n    node [shape=box];\n");
+        let mut next_id = 0;
+        self.write_dot(&mut out, &mut next_id, None, "");
+        out.push_str("}\n");
+        out
+    }
+
+    fn write_dot(&self, out: &mut String, next_id: &mut usize, parent: Option<usize>, label: &str) {
+        let id = *next_id;
+        *next_id += 1;
+
+        let b = &self.boundary;
+        out.push_str(&format!(
+            "    n{} [label=\"({}, {}) ±{},{}\\npoints={}\"];\n",
+            id, b.x, b.y, b.w, b.h, self.points.len()
+        ));
+        if let Some(parent) = parent {
+            out.push_str(&format!("    n{} -> n{} [label=\"{}\"];\n", parent, id, label));
+        }
+
+        for (child, label) in self.children().zip(["TL", "TR", "BL", "BR"]) {
+            child.write_dot(out, next_id, Some(id), label);
+        }
+    }
+
+    /// Query the quadtree for points within a rectangle, collecting f of each instead of a clone of the point
+    pub fn query_rect_map<U, F: Fn(&Point<T>) -> U>(&self, range: &Qrect, f: F) -> Vec<U> {
+        self.iter_rect(range).map(f).collect()
+    }
+
+    /// Remove and return every point within a rectangle
+    pub fn drain_rect(&mut self, range: &Qrect) -> Vec<Point<T>> {
+        let mut drained = vec![];
+        self.drain_matching(range, &|point| range.contains_point(point), &mut |point| drained.push(point));
+        drained
+    }
+
+    /// Remove and return every point within a circle, same bounds as query_circle
+    pub fn drain_circle(&mut self, cx: f32, cy: f32, r: f32) -> Vec<Point<T>> {
+        let mut drained = vec![];
+        self.drain_matching(&Qrect::new(cx, cy, r, r), &|point| (point.x - cx).powi(2) + (point.y - cy).powi(2) < r * r, &mut |point| drained.push(point));
+        drained
+    }
+
+    /// Remove every point within a rectangle, returns how many were removed
+    pub fn clear_rect(&mut self, range: &Qrect) -> usize {
+        self.drain_matching(range, &|point| range.contains_point(point), &mut drop)
+    }
+
+    /// Remove every point within a circle, returns how many were removed
+    pub fn clear_circle(&mut self, cx: f32, cy: f32, r: f32) -> usize {
+        self.drain_matching(&Qrect::new(cx, cy, r, r), &|point| (point.x - cx).powi(2) + (point.y - cy).powi(2) < r * r, &mut drop)
+    }
+
+    /// Remove and return every point matching f, wherever it is in the tree
+    pub fn take_matching<F: Fn(&Point<T>) -> bool>(&mut self, f: F) -> Vec<Point<T>> {
+        let mut taken = vec![];
+        let bounds = self.boundary.clone();
+        self.drain_matching(&bounds, &f, &mut |point| taken.push(point));
+        taken
+    }
+
+    // hands every point passing hit to sink, only nodes touching bounds are visited
+    // and children emptied along the way are folded back into their parent
+    fn drain_matching(&mut self, bounds: &Qrect, hit: &impl Fn(&Point<T>) -> bool, sink: &mut impl FnMut(Point<T>)) -> usize {
+        if !self.boundary.intersects_rect(bounds) {
+            return 0
+        }
+
+        let mut removed = 0;
+        let mut i = 0;
+        while i < self.points.len() {
+            if hit(&self.points[i]) {
+                sink(self.points.remove(i));
+                removed += 1;
+            } else {
+                i += 1;
+            }
+        }
+
+        for child in [&mut self.top_left, &mut self.top_right, &mut self.bottom_left, &mut self.bottom_right] {
+            if let Some(child) = child.as_mut() {
+                removed += child.drain_matching(bounds, hit, sink);
+            }
+        }
+
+        if removed > 0 {
+            self.count -= removed;
+            self.generation = next_generation();
+            self.collapse_empty_children();
+        }
+        removed
+    }
+
+    /// true if any point is less than epsilon from (x, y). walks down through the node holding (x, y)
+    /// first and only branches into neighbouring nodes the circle reaches, so a small epsilon is close
+    /// to a single descent
+    pub fn point_exists_near(&self, x: f32, y: f32, epsilon: f32) -> bool {
+        self.exists_near(x, y, epsilon * epsilon)
+    }
+
+    // recursive rather than stack based so a lookup doesn't allocate
+    fn exists_near(&self, x: f32, y: f32, eps_sq: f32) -> bool {
+        if self.points.iter().any(|point| (point.x - x).powi(2) + (point.y - y).powi(2) < eps_sq) {
+            return true
+        }
+        if !self.divided {
+            return false
+        }
+
+        let children = [&self.top_left, &self.top_right, &self.bottom_left, &self.bottom_right].map(|child| child.as_deref().unwrap());
+        let home = children.iter().position(|child| child.boundary.distance_sq_to(x, y) == 0.);
+        if let Some(home) = home {
+            if children[home].exists_near(x, y, eps_sq) {
+                return true
+            }
+        }
+        children.iter().enumerate().any(|(i, child)| {
+            Some(i) != home && child.boundary.distance_sq_to(x, y) < eps_sq && child.exists_near(x, y, eps_sq)
+        })
+    }
+
+}
+
+
+impl<T: Clone + Default> Quadtree<T> {
+    /// merge clusters of points within epsilon of each other into a single point at the
+    /// cluster centroid with default data, returns the number of points removed
+    pub fn merge_nearby_points(&mut self, epsilon: f32) -> usize {
+        let points = self.collect();
+
+        // index tree so each cluster can be found with query_circle
+        let mut index: Quadtree<usize> = Quadtree::new(self.boundary.clone(), self.capacity);
+        for (i, point) in points.iter().enumerate() {
+            index.insert(&Point::new(point.x, point.y, i));
+        }
+
+        let mut merged = vec![false; points.len()];
+        let mut kept = vec![];
+
+        for (i, point) in points.iter().enumerate() {
+            if merged[i] { continue }
+
+            let cluster: Vec<usize> = index.query_circle(point.x, point.y, epsilon)
+                .into_iter()
+                .map(|p| p.data)
+                .filter(|&j| !merged[j])
+                .collect();
+
+            if cluster.len() <= 1 {
+                merged[i] = true;
+                kept.push(point.clone());
+                continue
+            }
+
+            let mut sum_x = 0.; let mut sum_y = 0.;
+            for &j in &cluster {
+                merged[j] = true;
+                sum_x += points[j].x;
+                sum_y += points[j].y;
+            }
+            let n = cluster.len() as f32;
+            kept.push(Point::new(sum_x / n, sum_y / n, T::default()));
+        }
+
+        self.empty();
+        for point in &kept {
+            self.insert(point);
+        }
+
+        points.len() - kept.len()
+    }
+}
+
+
+/// Lazy iterator over references to the points of a quadtree within a rectangle,
+/// reversing it walks the quadrants and points back to front
+pub struct RectIter<'a, T: Clone> {
+    range: Qrect,
+    // front and back both work from this, so neither end sees what the other took
+    pending: VecDeque<Pending<'a, T>>,
+}
+
+// a node still to be opened or the points of one already opened
+enum Pending<'a, T: Clone> {
+    Node(&'a Quadtree<T>),
+    Points(std::slice::Iter<'a, Point<T>>),
+}
+
+impl<'a, T: Clone> Iterator for RectIter<'a, T> {
+    type Item = &'a Point<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.pending.front_mut()? {
+                Pending::Points(points) => {
+                    for point in points.by_ref() {
+                        if self.range.contains_point(point) {
+                            return Some(point)
+                        }
+                    }
+                }
+                Pending::Node(node) => {
+                    let node: &'a Quadtree<T> = node;
+                    self.pending.pop_front();
+                    if !node.boundary.intersects_rect(&self.range) {
+                        continue
+                    }
+
+                    // a node's own points come before its children, matching query_rect
+                    let children: Vec<&Quadtree<T>> = node.children().collect();
+                    for child in children.into_iter().rev() {
+                        self.pending.push_front(Pending::Node(child));
+                    }
+                    self.pending.push_front(Pending::Points(node.points.iter()));
+                    continue
+                }
+            }
+            self.pending.pop_front();
+        }
+    }
+}
+
+impl<'a, T: Clone> DoubleEndedIterator for RectIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.pending.back_mut()? {
+                Pending::Points(points) => {
+                    while let Some(point) = points.next_back() {
+                        if self.range.contains_point(point) {
+                            return Some(point)
+                        }
+                    }
+                }
+                Pending::Node(node) => {
+                    let node: &'a Quadtree<T> = node;
+                    self.pending.pop_back();
+                    if !node.boundary.intersects_rect(&self.range) {
+                        continue
+                    }
+
+                    self.pending.push_back(Pending::Points(node.points.iter()));
+                    for child in node.children() {
+                        self.pending.push_back(Pending::Node(child));
+                    }
+                    continue
+                }
+            }
+            self.pending.pop_back();
+        }
+    }
+}
+
+
+impl<T: Clone + PartialEq> Quadtree<T> {
+    /// Remove the first point matching position and data, returns false if it wasn't found
+    pub fn remove(&mut self, point: &Point<T>) -> bool {
+        self.remove_inner(point, true)
+    }
+
+    /// Remove every listed point and collapse emptied subtrees once at the end,
+    /// returns how many were removed
+    pub fn remove_all(&mut self, points: &[Point<T>]) -> usize {
+        let removed = points.iter().filter(|point| self.remove_inner(point, false)).count();
+        self.collapse_empty_subtrees();
+        removed
+    }
+
+    fn remove_inner(&mut self, point: &Point<T>, collapse: bool) -> bool {
+        if !self.boundary.contains_point(point) {
+            return false
+        }
+
+        if let Some(i) = self.points.iter().position(|p| p == point) {
+            self.points.remove(i);
+            self.generation = next_generation();
+            self.count -= 1;
+            return true
+        }
+
+        if !self.divided {
+            return false
+        }
+
+        let removed = self.top_left.as_mut().unwrap().remove_inner(point, collapse)
+            || self.top_right.as_mut().unwrap().remove_inner(point, collapse)
+            || self.bottom_left.as_mut().unwrap().remove_inner(point, collapse)
+            || self.bottom_right.as_mut().unwrap().remove_inner(point, collapse);
+
+        if removed {
+            self.count -= 1;
+            if collapse {
+                self.collapse_empty_children();
+            }
+        }
+        removed
+    }
+
+    /// Capture the current points so they can be diffed later. points are sorted by (x, y)
+    /// rather than kept in traversal order so the snapshot doesn't change with the split structure
+    pub fn snapshot(&self) -> QuadtreeSnapshot<T> {
+        let mut points = self.collect();
+        points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+        QuadtreeSnapshot { points }
+    }
+
+    /// Apply a diff taken between two snapshots
+    pub fn apply_diff(&mut self, diff: &SnapshotDiff<T>) {
+        for point in &diff.removed {
+            self.remove(point);
+        }
+        for point in &diff.added {
+            self.insert(point);
+        }
+    }
+}
+
+
+/// The points of a quadtree captured at one moment
+#[derive(Clone, Debug)]
+pub struct QuadtreeSnapshot<T: Clone> {
+    pub points: Vec<Point<T>>,
+}
+impl<T: Clone + PartialEq> QuadtreeSnapshot<T> {
+    /// the points added and removed going from before to after
+    pub fn diff(before: &Self, after: &Self) -> SnapshotDiff<T> {
+        SnapshotDiff {
+            added: multiset_difference(&after.points, &before.points),
+            removed: multiset_difference(&before.points, &after.points),
+        }
+    }
+}
+
+/// Changes between two snapshots
+#[derive(Clone, Debug)]
+pub struct SnapshotDiff<T: Clone> {
+    pub added: Vec<Point<T>>,
+    pub removed: Vec<Point<T>>,
+}
+impl<T: Clone + PartialEq> SnapshotDiff<T> {
+    /// the diff that undoes this one
+    pub fn inverse(&self) -> Self {
+        SnapshotDiff { added: self.removed.clone(), removed: self.added.clone() }
+    }
+
+    /// Undo this diff on tree, the opposite of Quadtree::apply_diff
+    pub fn revert(&self, tree: &mut Quadtree<T>) {
+        tree.apply_diff(&self.inverse());
+    }
+}
+
+// points in a that aren't matched one to one by points in b
+fn multiset_difference<T: Clone + PartialEq>(a: &[Point<T>], b: &[Point<T>]) -> Vec<Point<T>> {
+    let mut used = vec![false; b.len()];
+    let mut out = vec![];
+    for point in a {
+        match (0..b.len()).find(|&i| !used[i] && b[i] == *point) {
+            Some(i) => used[i] = true,
+            None => out.push(point.clone()),
+        }
+    }
+    out
+}
+
+
+/// All points found in either a or b, duplicates are only kept once
+pub fn union_points<T: Clone + PartialEq>(a: &[Point<T>], b: &[Point<T>]) -> Vec<Point<T>> {
+    let mut out: Vec<Point<T>> = vec![];
+    for point in a.iter().chain(b) {
+        if !out.contains(point) {
+            out.push(point.clone());
+        }
+    }
+    out
+}
+
+/// All points found in both a and b, duplicates are only kept once
+pub fn intersect_points<T: Clone + PartialEq>(a: &[Point<T>], b: &[Point<T>]) -> Vec<Point<T>> {
+    let mut out: Vec<Point<T>> = vec![];
+    for point in a {
+        if b.contains(point) && !out.contains(point) {
+            out.push(point.clone());
+        }
+    }
+    out
+}
+
+/// All points in a that aren't in b, duplicates are only kept once
+pub fn difference_points<T: Clone + PartialEq>(a: &[Point<T>], b: &[Point<T>]) -> Vec<Point<T>> {
+    let mut out: Vec<Point<T>> = vec![];
+    for point in a {
+        if !b.contains(point) && !out.contains(point) {
+            out.push(point.clone());
+        }
+    }
+    out
+}
+
+
+/// capacity used when a quadtree is built without one being given
+pub const DEFAULT_CAPACITY: usize = 4;
+
+fn bounding_box_of<T: Clone>(points: &[Point<T>]) -> Option<Qrect> {
+    let first = points.first()?;
+    let mut min = (first.x, first.y);
+    let mut max = (first.x, first.y);
+    for point in points {
+        min = (min.0.min(point.x), min.1.min(point.y));
+        max = (max.0.max(point.x), max.1.max(point.y));
+    }
+    Some(Qrect::corners(min, max))
+}
+
+impl<T: Clone> From<Quadtree<T>> for Vec<Point<T>> {
+    fn from(qt: Quadtree<T>) -> Self {
+        qt.into_vec()
+    }
+}
+
+impl<T: Clone> AsRef<Quadtree<T>> for Quadtree<T> {
+    fn as_ref(&self) -> &Quadtree<T> {
+        self
+    }
+}
+
+/// Builds a quadtree around the bounding box of the points with DEFAULT_CAPACITY.
+/// panics if the Vec is empty
+impl<T: Clone + Default> From<Vec<Point<T>>> for Quadtree<T> {
+    fn from(points: Vec<Point<T>>) -> Self {
+        Quadtree::from_vec(points, DEFAULT_CAPACITY)
+    }
+}
+
+
+/// leaves holding at least this many points are filtered in simd batches when the `simd` feature is on
+pub const SIMD_LEAF_THRESHOLD: usize = 16;
+
+/// push the points contained by range onto found, one at a time
+fn filter_in_rect_scalar<T: Clone>(points: &[Point<T>], range: &Qrect, found: &mut Vec<Point<T>>) {
+    for point in points {
+        if range.contains_point(point) {
+            found.push(point.clone());
+        }
+    }
+}
+
+/// push the points contained by range onto found, testing eight at a time
+#[cfg(feature = "simd")]
+fn filter_in_rect_simd<T: Clone>(points: &[Point<T>], range: &Qrect, found: &mut Vec<Point<T>>) {
+    use wide::*;
+
+    let min_x = f32x8::splat(range.x - range.w);
+    let max_x = f32x8::splat(range.x + range.w);
+    let min_y = f32x8::splat(range.y - range.h);
+    let max_y = f32x8::splat(range.y + range.h);
+
+    let chunks = points.chunks_exact(8);
+    let remainder = chunks.remainder();
+    for chunk in chunks {
+        let xs = f32x8::from(std::array::from_fn::<f32, 8, _>(|i| chunk[i].x));
+        let ys = f32x8::from(std::array::from_fn::<f32, 8, _>(|i| chunk[i].y));
+        let inside = xs.cmp_ge(min_x) & xs.cmp_le(max_x) & ys.cmp_ge(min_y) & ys.cmp_le(max_y);
+        let mask = inside.move_mask();
+        for (i, point) in chunk.iter().enumerate() {
+            if mask & (1 << i) != 0 {
+                found.push(point.clone());
+            }
+        }
+    }
+    filter_in_rect_scalar(remainder, range, found);
+}
+
+/// push the points contained by range onto found, batching dense leaves when simd is available
+fn filter_in_rect<T: Clone>(points: &[Point<T>], range: &Qrect, found: &mut Vec<Point<T>>) {
+    #[cfg(feature = "simd")]
+    if points.len() >= SIMD_LEAF_THRESHOLD {
+        return filter_in_rect_simd(points, range, found)
+    }
+
+    filter_in_rect_scalar(points, range, found);
+}
+
+fn polygon_aabb(vertices: &[(f32, f32)]) -> Option<Qrect> {
+    let first = vertices.first()?;
+    let mut min = *first;
+    let mut max = *first;
+    for &(x, y) in vertices {
+        min = (min.0.min(x), min.1.min(y));
+        max = (max.0.max(x), max.1.max(y));
+    }
+    Some(Qrect::corners(min, max))
+}
+
+
+/// true as soon as any of the trees has a point within range
+pub fn any_in_rect_multi<'a, T: Clone + 'a>(trees: impl IntoIterator<Item = &'a Quadtree<T>>, range: &Qrect) -> bool {
+    trees.into_iter().any(|tree| tree.intersects_any_point(range))
+}
+
+
+impl<T: Clone + std::fmt::Display> Quadtree<T> {
+    /// Write an x,y,data row for every point within range straight to the writer
+    pub fn write_rect_csv<W: std::io::Write>(&self, range: &Qrect, w: &mut W) -> std::io::Result<()> {
+        for point in self.iter_rect(range) {
+            writeln!(w, "{},{},{}", point.x, point.y, point.data)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Which comma separated columns hold x, y and the data when importing csv
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvConfig {
+    pub x_col: usize,
+    pub y_col: usize,
+    pub data_col: usize,
+}
+impl Default for CsvConfig {
+    fn default() -> Self {
+        Self { x_col: 0, y_col: 1, data_col: 2 }
+    }
+}
+
+/// A csv row that couldn't be imported, line numbers start at 1
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvLineError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// Every row that failed during Quadtree::insert_batch_from_csv, the rows that parsed were still inserted
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvImportError {
+    pub inserted: usize,
+    pub errors: Vec<CsvLineError>,
+}
+
+impl<T: Clone + std::str::FromStr> Quadtree<T> where T::Err: std::fmt::Display {
+    /// Insert a point for every row of reader, blank lines are skipped.
+    /// returns how many points went in, or every failing row with its line number if any failed
+    pub fn insert_batch_from_csv(&mut self, reader: impl std::io::BufRead, config: &CsvConfig) -> Result<usize, CsvImportError> {
+        let mut inserted = 0;
+        let mut errors = vec![];
+        for (i, line) in reader.lines().enumerate() {
+            let line_no = i + 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    // the reader is broken, nothing after this can be trusted
+                    errors.push(CsvLineError { line: line_no, message: format!("read failed: {}", e) });
+                    break
+                }
+            };
+            if line.trim().is_empty() {
+                continue
+            }
+
+            match parse_csv_row(&line, config) {
+                Ok(point) => {
+                    if self.insert(&point) {
+                        inserted += 1;
+                    } else {
+                        errors.push(CsvLineError { line: line_no, message: format!("({}, {}) is outside the boundary", point.x, point.y) });
+                    }
+                }
+                Err(message) => errors.push(CsvLineError { line: line_no, message }),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(inserted)
+        } else {
+            Err(CsvImportError { inserted, errors })
+        }
+    }
+}
+
+fn parse_csv_row<T: Clone + std::str::FromStr>(line: &str, config: &CsvConfig) -> Result<Point<T>, String> where T::Err: std::fmt::Display {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let field = |col: usize, name: &str| {
+        fields.get(col).copied().ok_or_else(|| format!("missing {} column {}", name, col))
+    };
+
+    let x: f32 = field(config.x_col, "x")?.parse().map_err(|e| format!("bad x: {}", e))?;
+    let y: f32 = field(config.y_col, "y")?.parse().map_err(|e| format!("bad y: {}", e))?;
+    let data: T = field(config.data_col, "data")?.parse().map_err(|e| format!("bad data: {}", e))?;
+    Ok(Point::new(x, y, data))
+}
+
+
+/// how many levels below the root Quadtree::bulk_load may split, deeper nodes keep every point they get
+pub const BULK_LOAD_MAX_DEPTH: usize = 32;
+
+// where to cut values sorted ascending so the first mid of them fall on the low side, halfway
+// between the neighbours so no point sits on the cut unless they share it. fallback when one side is empty
+fn median_cut(sorted: impl Iterator<Item = f32>, mid: usize, fallback: f32) -> f32 {
+    let mut sorted = sorted.skip(mid.saturating_sub(1));
+    match (mid, sorted.next(), sorted.next()) {
+        (1.., Some(low), Some(high)) => low + (high - low) / 2.,
+        _ => fallback,
+    }
+}
+
+
+/// magic bytes opening every stream written by Quadtree::write_to_writer
+pub const STREAM_MAGIC: [u8; 4] = *b"QTSS";
+/// format version written after STREAM_MAGIC, bumped whenever the layout changes
+pub const STREAM_VERSION: u32 = 1;
+
+#[cfg(feature = "bytemuck")]
+impl<T: Clone + bytemuck::Pod> Quadtree<T> {
+    /// Stream the tree depth first to writer: a header of magic, version, data size, boundary and capacity,
+    /// then for every node its point count, points, divided flag and children. the flag is 0 for a leaf,
+    /// 1 for a node split at its center and 2 for one split off center by bulk_load, followed by the
+    /// four child boundaries. the quantization step is not saved
+    pub fn write_to_writer<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&STREAM_MAGIC)?;
+        writer.write_all(&STREAM_VERSION.to_le_bytes())?;
+        writer.write_all(&(std::mem::size_of::<T>() as u32).to_le_bytes())?;
+        for v in [self.boundary.x, self.boundary.y, self.boundary.w, self.boundary.h] {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+        writer.write_all(&(self.capacity as u64).to_le_bytes())?;
+        self.write_node(writer)
+    }
+
+    fn write_node<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(&(self.points.len() as u64).to_le_bytes())?;
+        for point in &self.points {
+            writer.write_all(&point.x.to_le_bytes())?;
+            writer.write_all(&point.y.to_le_bytes())?;
+            writer.write_all(&[point.layer])?;
+            writer.write_all(bytemuck::bytes_of(&point.data))?;
+        }
+        if self.split_at_center() {
+            writer.write_all(&[self.divided as u8])?;
+        } else {
+            writer.write_all(&[2])?;
+            for child in self.children() {
+                for v in [child.boundary.x, child.boundary.y, child.boundary.w, child.boundary.h] {
+                    writer.write_all(&v.to_le_bytes())?;
+                }
+            }
+        }
+        for child in self.children() {
+            child.write_node(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild a tree streamed by write_to_writer, node for node
+    pub fn read_from_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != STREAM_MAGIC {
+            return Err(invalid_stream(format!("bad magic bytes {:?}, expected {:?}", magic, STREAM_MAGIC)))
+        }
+        let version = read_u32(reader)?;
+        if version != STREAM_VERSION {
+            return Err(invalid_stream(format!("unsupported version {}, expected {}", version, STREAM_VERSION)))
+        }
+        let data_size = read_u32(reader)? as usize;
+        if data_size != std::mem::size_of::<T>() {
+            return Err(invalid_stream(format!("data is {} bytes per point, expected {}", data_size, std::mem::size_of::<T>())))
+        }
+
+        let boundary = Qrect::new(read_f32(reader)?, read_f32(reader)?, read_f32(reader)?, read_f32(reader)?);
+        let capacity = read_u64(reader)? as usize;
+        let mut qt = Quadtree::new(boundary, capacity);
+        qt.read_node(reader)?;
+        Ok(qt)
+    }
+
+    fn read_node<R: std::io::Read>(&mut self, reader: &mut R) -> std::io::Result<()> {
+        let count = read_u64(reader)?;
+        for _ in 0..count {
+            let x = read_f32(reader)?;
+            let y = read_f32(reader)?;
+            let mut layer = [0; 1];
+            reader.read_exact(&mut layer)?;
+            let mut data: T = bytemuck::Zeroable::zeroed();
+            reader.read_exact(bytemuck::bytes_of_mut(&mut data))?;
+            let mut point = Point::new(x, y, data);
+            point.layer = layer[0];
+            self.points.push(point);
+        }
+
+        let mut divided = [0; 1];
+        reader.read_exact(&mut divided)?;
+        match divided[0] {
+            0 => {}
+            flag @ (1 | 2) => {
+                if flag == 1 {
+                    self.subdivide();
+                } else {
+                    let mut read_rect = || -> std::io::Result<Qrect> {
+                        Ok(Qrect::new(read_f32(reader)?, read_f32(reader)?, read_f32(reader)?, read_f32(reader)?))
+                    };
+                    self.subdivide_into([read_rect()?, read_rect()?, read_rect()?, read_rect()?]);
+                }
+                self.top_left.as_mut().unwrap().read_node(reader)?;
+                self.top_right.as_mut().unwrap().read_node(reader)?;
+                self.bottom_left.as_mut().unwrap().read_node(reader)?;
+                self.bottom_right.as_mut().unwrap().read_node(reader)?;
+            }
+            flag => return Err(invalid_stream(format!("bad divided flag {}", flag))),
+        }
+        self.recount();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "bytemuck")]
+fn invalid_stream(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(feature = "bytemuck")]
+fn read_u32<R: std::io::Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+#[cfg(feature = "bytemuck")]
+fn read_u64<R: std::io::Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(feature = "bytemuck")]
+fn read_f32<R: std::io::Read>(reader: &mut R) -> std::io::Result<f32> {
+    Ok(f32::from_bits(read_u32(reader)?))
+}
+
+
+/// how many times over capacity a node may grow before adaptive_insert rebalances it
+pub const REBALANCE_THRESHOLD: usize = 8;
+
+/// how many times len must grow after a rebuild before with_auto_rebalance rebuilds again
+pub const AUTO_REBALANCE_GROWTH: usize = 2;
+
+/// Where a point went after Quadtree::adaptive_insert
+#[derive(Clone, Debug, PartialEq)]
+pub enum InsertResult {
+    Inserted { depth: usize },
+    Rejected { reason: InsertRejection },
+    Rebalanced { depth: usize, old_node_count: usize, new_node_count: usize },
+}
+
+/// Why Quadtree::adaptive_insert turned a point away
+#[derive(Clone, Debug, PartialEq)]
+pub enum InsertRejection {
+    OutOfBounds,
+    NonFinite,
+}
+
+
+#[cfg(feature = "bitvec")]
+impl Quadtree<usize> {
+    /// For a quadtree whose data are indices into external storage, a mask of total bits
+    /// with the bit set for every index inside range, indices past total are ignored
+    pub fn query_rect_mask(&self, range: &Qrect, total: usize) -> bitvec::vec::BitVec {
+        let mut mask = bitvec::vec::BitVec::repeat(false, total);
+        for point in self.iter_rect(range) {
+            if point.data < total {
+                mask.set(point.data, true);
+            }
+        }
+        mask
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Clone + Send + Sync> Quadtree<T> {
+    /// Rebuild the tree from its own points like bulk_load, loading the four root quadrants on separate threads
+    pub fn rebuild_parallel(&mut self) {
+        let points = self.collect();
+        self.empty();
+
+        if let Some([tl_points, tr_points, bl_points, br_points]) = self.load_leaf_or_split(points, 0) {
+            let top_left = self.top_left.as_deref_mut().unwrap();
+            let top_right = self.top_right.as_deref_mut().unwrap();
+            let bottom_left = self.bottom_left.as_deref_mut().unwrap();
+            let bottom_right = self.bottom_right.as_deref_mut().unwrap();
+            rayon::join(
+                || rayon::join(|| top_left.bulk_load_node(tl_points, 1), || top_right.bulk_load_node(tr_points, 1)),
+                || rayon::join(|| bottom_left.bulk_load_node(bl_points, 1), || bottom_right.bulk_load_node(br_points, 1)),
+            );
+        }
+    }
+
+    /// Parallel iterator over references to the points within a rectangle. the nodes touching range
+    /// are gathered up front and their points are filtered on the rayon pool as the iterator is driven
+    pub fn par_iter_rect(&self, range: &Qrect) -> impl rayon::iter::ParallelIterator<Item = &Point<T>> + '_ {
+        use rayon::prelude::*;
+
+        let mut nodes = vec![];
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if !node.boundary.intersects_rect(range) {
+                continue
+            }
+            if !node.points.is_empty() {
+                nodes.push(node);
+            }
+            stack.extend(node.children());
+        }
+
+        let range = range.clone();
+        nodes.into_par_iter().flat_map_iter(move |node| {
+            let range = range.clone();
+            node.points.iter().filter(move |point| range.contains_point(*point))
+        })
+    }
+}
+
+
+#[cfg(feature = "rand")]
+impl<T: Clone> Quadtree<T> {
+    /// Estimate the number of points by sampling each point with probability sample_fraction and
+    /// scaling the number sampled by its inverse. the gaps between samples are drawn directly, so a
+    /// subtree the next sample lands beyond is stepped over whole using its cached count and only
+    /// the nodes on the way to sampled points are visited. the estimate is unbiased with a relative
+    /// error around sqrt((1 - f) / (f * len)), about 4% at f = 0.1 over 5000 points. len() is exact,
+    /// this is for checking sampling code against a known answer. a NaN fraction samples nothing
+    pub fn estimate_count_sampled(&self, sample_fraction: f32, rng: &mut impl rand::Rng) -> usize {
+        let fraction = if sample_fraction.is_nan() { 0. } else { sample_fraction.clamp(0., 1.) as f64 };
+        if fraction == 0. {
+            return 0
+        }
+
+        // points passed over before the next sample, geometric with success chance fraction
+        let mut gap = || -> usize {
+            if fraction == 1. {
+                return 0
+            }
+            let u: f64 = 1. - rng.gen::<f64>();
+            (u.ln() / (1. - fraction).ln()) as usize
+        };
+
+        let mut sampled = 0;
+        let mut skip = gap();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if skip >= node.count {
+                skip -= node.count;
+                continue
+            }
+
+            let mut remaining = node.points.len();
+            while skip < remaining {
+                sampled += 1;
+                remaining -= skip + 1;
+                skip = gap();
+            }
+            skip -= remaining;
+            stack.extend(node.children());
+        }
+        (sampled as f64 / fraction).round() as usize
+    }
+}
+
+#[cfg(feature = "geojson")]
+impl<T: Clone + serde::Serialize> Quadtree<T> {
+    /// Every point as a GeoJSON FeatureCollection, each feature is a Point geometry at [x, y]
+    /// with the data under properties.data. data that fails to serialize becomes null
+    pub fn to_geojson(&self) -> String {
+        geojson_collection(self.iter())
+    }
+
+    /// The points within range as a GeoJSON FeatureCollection, see to_geojson
+    pub fn to_geojson_rect(&self, range: &Qrect) -> String {
+        geojson_collection(self.iter_rect(range))
+    }
+}
+
+#[cfg(feature = "geojson")]
+fn geojson_collection<'a, T: Clone + serde::Serialize + 'a>(points: impl Iterator<Item = &'a Point<T>>) -> String {
+    let features: Vec<serde_json::Value> = points
+        .map(|point| {
+            let data = serde_json::to_value(&point.data).unwrap_or(serde_json::Value::Null);
+            serde_json::json!({
+                "type": "Feature",
+                "geometry": { "type": "Point", "coordinates": [point.x, point.y] },
+                "properties": { "data": data },
+            })
+        })
+        .collect();
+    serde_json::json!({ "type": "FeatureCollection", "features": features }).to_string()
+}
+
+
+static GENERATION: AtomicU64 = AtomicU64::new(1);
+// how many stamps a thread takes from GENERATION at once
+const GENERATION_BLOCK: u64 = 1 << 16;
+
+thread_local! {
+    // the next stamp this thread hands out and the end of its block
+    static THREAD_GENERATION: Cell<(u64, u64)> = const { Cell::new((0, 0)) };
+}
+
+// stamps are unique across every tree so a stamp can't be mistaken for one from another tree.
+// each thread draws them from its own block so unrelated trees on different threads don't
+// fight over one counter, which means stamps are unique but not ordered across threads
+fn next_generation() -> u64 {
+    THREAD_GENERATION.with(|block| {
+        let (mut stamp, mut end) = block.get();
+        if stamp == end {
+            stamp = GENERATION.fetch_add(GENERATION_BLOCK, Ordering::Relaxed);
+            end = stamp + GENERATION_BLOCK;
+        }
+        block.set((stamp + 1, end));
+        stamp
+    })
+}
+
+/// Remembers the last rect query and reuses its result while nothing in the region changed
+pub struct CachedQuery<T: Clone> {
+    range: Option<Qrect>,
+    result: Vec<Point<T>>,
+    generation: u64,
+    /// number of calls answered from the cache
+    pub hits: usize,
+}
+impl<T: Clone> CachedQuery<T> {
+    pub fn new() -> Self {
+        Self { range: None, result: vec![], generation: 0, hits: 0 }
+    }
+
+    /// Query tree for points within range, reusing the last result when the range is the same
+    /// and the region's generation hasn't moved
+    pub fn get(&mut self, tree: &Quadtree<T>, range: &Qrect) -> &Vec<Point<T>> {
+        let generation = tree.region_generation(range);
+        if self.range.as_ref() == Some(range) && self.generation == generation {
+            self.hits += 1;
+        } else {
+            self.range = Some(range.clone());
+            self.result = tree.query_rect(range);
+            self.generation = generation;
+        }
+        &self.result
+    }
+}
+impl<T: Clone> Default for CachedQuery<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Handle to a point stored in a MetadataQuadtree
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PointId(u64);
+
+/// A quadtree that keeps application metadata next to its points instead of inside their data.
+/// the tree only indexes ids, the points and their metadata live in side tables. this is its own
+/// type rather than a method on Quadtree because Quadtree's points have no identity, two points
+/// with the same position and data are interchangeable and nodes move points around as they split
+/// and rebalance, so there is nothing stable to hang metadata on. handing out a PointId gives
+/// every point one, which is what lets metadata be looked up, changed and removed later
+pub struct MetadataQuadtree<T: Clone, M> {
+    tree: Quadtree<PointId>,
+    points: HashMap<PointId, Point<T>>,
+    metadata: HashMap<PointId, M>,
+    next_id: u64,
+}
+impl<T: Clone, M> MetadataQuadtree<T, M> {
+    pub fn new(boundary: Qrect, capacity: usize) -> Self {
+        Self { tree: Quadtree::new(boundary, capacity), points: HashMap::new(), metadata: HashMap::new(), next_id: 0 }
+    }
+
+    /// Insert a point without metadata, None if it falls outside the boundary
+    pub fn insert(&mut self, point: &Point<T>) -> Option<PointId> {
+        let id = PointId(self.next_id);
+        let mut entry = Point::new(point.x, point.y, id);
+        entry.layer = point.layer;
+        if !self.tree.insert(&entry) {
+            return None
+        }
+
+        self.next_id += 1;
+        self.points.insert(id, point.clone());
+        Some(id)
+    }
+
+    /// Insert a point tagged with metadata, None if it falls outside the boundary
+    pub fn insert_with_metadata(&mut self, point: &Point<T>, metadata: M) -> Option<PointId> {
+        let id = self.insert(point)?;
+        self.metadata.insert(id, metadata);
+        Some(id)
+    }
+
+    pub fn get(&self, id: PointId) -> Option<&Point<T>> {
+        self.points.get(&id)
+    }
+
+    pub fn get_metadata(&self, id: PointId) -> Option<&M> {
+        self.metadata.get(&id)
+    }
+
+    pub fn get_metadata_mut(&mut self, id: PointId) -> Option<&mut M> {
+        self.metadata.get_mut(&id)
+    }
+
+    /// Tag a stored point with metadata, returning whatever it was tagged with before.
+    /// does nothing and returns None if id isn't stored
+    pub fn set_metadata(&mut self, id: PointId, metadata: M) -> Option<M> {
+        if !self.points.contains_key(&id) {
+            return None
+        }
+        self.metadata.insert(id, metadata)
+    }
+
+    /// Drop a point's metadata but keep the point
+    pub fn remove_metadata(&mut self, id: PointId) -> Option<M> {
+        self.metadata.remove(&id)
+    }
+
+    /// Remove a point and its metadata, None if id isn't stored
+    pub fn remove(&mut self, id: PointId) -> Option<(Point<T>, Option<M>)> {
+        let point = self.points.remove(&id)?;
+        self.tree.remove(&Point::new(point.x, point.y, id));
+        Some((point, self.metadata.remove(&id)))
+    }
+
+    /// Move a stored point to (x, y) keeping its id and metadata. false and left where it was if
+    /// id isn't stored or (x, y) is outside the boundary
+    pub fn move_point(&mut self, id: PointId, x: f32, y: f32) -> bool {
+        let Some(point) = self.points.get_mut(&id) else { return false };
+        let mut entry = Point::new(x, y, id);
+        entry.layer = point.layer;
+        if !self.tree.insert(&entry) {
+            return false
+        }
+
+        self.tree.remove(&Point::new(point.x, point.y, id));
+        point.x = x;
+        point.y = y;
+        true
+    }
+
+    /// Query for points within a rectangle along with whatever metadata they were tagged with
+    pub fn query_rect_with_metadata(&self, range: &Qrect) -> Vec<(&Point<T>, Option<&M>)> {
+        self.tree.iter_rect(range)
+            .map(|entry| (&self.points[&entry.data], self.metadata.get(&entry.data)))
+            .collect()
+    }
+
+    /// Query for points within range of (x, y) along with whatever metadata they were tagged with
+    pub fn query_circle_with_metadata(&self, x: f32, y: f32, range: f32) -> Vec<(&Point<T>, Option<&M>)> {
+        self.tree.query_circle(x, y, range)
+            .into_iter()
+            .map(|entry| (&self.points[&entry.data], self.metadata.get(&entry.data)))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+}
+
+
+/// Point counts of a quadtree node and its children in top left, top right, bottom left, bottom right order
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuadrantCounts {
+    /// points held directly by this node
+    pub count: usize,
+    pub children: Option<Box<[QuadrantCounts; 4]>>,
+}
+impl QuadrantCounts {
+    /// points held by this node and everything under it
+    pub fn total(&self) -> usize {
+        self.count + self.children.as_ref().map_or(0, |children| children.iter().map(|child| child.total()).sum())
+    }
+}
+
+
+/// One of the four children of a node
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Quadrant {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+impl Quadrant {
+    /// every quadrant in the order points are offered to children
+    pub const ALL: [Quadrant; 4] = [Quadrant::TopLeft, Quadrant::TopRight, Quadrant::BottomLeft, Quadrant::BottomRight];
+}
+
+/// The quadrants walked from the root to a node, empty for the root itself
+pub type NodePath = Vec<Quadrant>;
+
+
+/// tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_works(){
+        let size = 50.0;
+        let mut qt = Quadtree::new(Qrect::new(size, size, size, size), 4);
+        qt.insert(&Point::new(25., 25., 0));
+    }
+
+    #[test]
+    // keeps passing &Qrect to show the borrowed form from before query_rect took impl Into<Qrect> still compiles
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    fn insert_at_known_points() {
+        let size = 50.0;
+        let mut qt = Quadtree::new(Qrect::new(size, size, size, size), 4);
+        qt.insert(&Point::new(25., 25., 0));
+        qt.insert(&Point::new(25., 25., 1));
+        qt.insert(&Point::new(25., 25., 2));
+        qt.insert(&Point::new(25., 25., 3));
+        // check
+        let found = qt.query_rect(&Qrect::range(25., 25., 1.));
+        assert_eq!(found.len(), 4);
+        // check locations
+        assert_eq!(found[0].data, 0);
+        assert_eq!(found[1].data, 1);
+        assert_eq!(found[2].data, 2);
+        assert_eq!(found[3].data, 3);
+    }
+
+    #[test]
+    fn merge_nearby_points_condenses_cluster() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        for i in 0..10 {
+            qt.insert(&Point::new(50. + i as f32 * 0.01, 50. - i as f32 * 0.01, i));
+        }
+        let removed = qt.merge_nearby_points(0.5);
+        assert_eq!(removed, 9);
+        let found = qt.collect();
+        assert_eq!(found.len(), 1);
+        assert!((found[0].x - 50.045).abs() < 0.001);
+        assert_eq!(found[0].data, 0);
+    }
+
+    #[test]
+    fn merge_nearby_points_leaves_separated_points() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        qt.insert(&Point::new(10., 10., 1));
+        qt.insert(&Point::new(50., 50., 2));
+        qt.insert(&Point::new(90., 90., 3));
+        assert_eq!(qt.merge_nearby_points(1.), 0);
+        assert_eq!(qt.collect().len(), 3);
+    }
+
+    #[test]
+    fn cloned_iter_rect_matches_query_rect() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..20 {
+            qt.insert(&Point::new((i * 5) as f32, (i * 3) as f32, i));
+        }
+        let range = Qrect::new(40., 30., 25., 20.);
+        let iterated: Vec<i32> = qt.cloned_iter_rect(&range).map(|p| p.data).collect();
+        let queried: Vec<i32> = qt.query_rect(&range).into_iter().map(|p| p.data).collect();
+        assert!(!queried.is_empty());
+        assert_eq!(iterated, queried);
+    }
+
+    #[test]
+    fn query_rect_sorted_by_distance_uses_focus() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        qt.insert(&Point::new(50., 50., 0));
+        qt.insert(&Point::new(40., 50., 1));
+        qt.insert(&Point::new(60., 50., 2));
+        qt.insert(&Point::new(30., 50., 3));
+        qt.insert(&Point::new(95., 50., 4));
+        // focus sits to the left of the rect center
+        let found = qt.query_rect_sorted_by_distance(&Qrect::new(50., 50., 25., 25.), (20., 50.));
+        let order: Vec<i32> = found.iter().map(|p| p.data).collect();
+        assert_eq!(order, vec![3, 1, 0, 2]);
+    }
+
+    #[test]
+    fn query_in_rotated_frame_selects_diagonal_band() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        qt.insert(&Point::new(30., 30., 0));
+        qt.insert(&Point::new(50., 50., 1));
+        qt.insert(&Point::new(70., 70., 2));
+        qt.insert(&Point::new(70., 30., 3));
+        qt.insert(&Point::new(30., 70., 4));
+        // a thin horizontal band rotated 45 degrees runs along the diagonal
+        let band = Qrect::new(50., 50., 40., 5.);
+        let angle = std::f32::consts::FRAC_PI_4;
+        let mut found: Vec<i32> = qt.query_in_rotated_frame(50., 50., angle, &band).iter().map(|p| p.data).collect();
+        found.sort();
+        assert_eq!(found, vec![0, 1, 2]);
+
+        let aabb = band.obb_aabb(50., 50., angle);
+        assert!((aabb.w - aabb.h).abs() < 0.001);
+        assert!((aabb.w - 45. * angle.cos()).abs() < 0.001);
+    }
+
+    #[test]
+    fn points_in_other_region_splits_by_tree() {
+        let mut a = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        let mut b = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        a.insert(&Point::new(20., 20., 1));
+        a.insert(&Point::new(80., 80., 2));
+        b.insert(&Point::new(25., 25., 3));
+        b.insert(&Point::new(15., 20., 4));
+        let (mine, theirs) = a.points_in_other_region(&b, &Qrect::new(20., 20., 10., 10.));
+        assert_eq!(mine.iter().map(|p| p.data).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(theirs.iter().map(|p| p.data).collect::<Vec<_>>(), vec![3, 4]);
+    }
+
+    #[test]
+    fn remove_point() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 1);
+        qt.insert(&Point::new(10., 10., 1));
+        qt.insert(&Point::new(90., 90., 2));
+        assert!(qt.remove(&Point::new(90., 90., 2)));
+        assert!(!qt.remove(&Point::new(90., 90., 2)));
+        assert_eq!(qt.collect().len(), 1);
+        assert_eq!(qt.get_rects().len(), 1);
+    }
+
+    #[test]
+    fn snapshot_diff_replays_changes() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..8 {
+            qt.insert(&Point::new((i * 10) as f32, (i * 10) as f32, i));
+        }
+        let original = qt.clone();
+        let before = qt.snapshot();
+
+        qt.remove(&Point::new(30., 30., 3));
+        qt.remove(&Point::new(60., 60., 6));
+        qt.insert(&Point::new(55., 15., 8));
+        let after = qt.snapshot();
+
+        let diff = QuadtreeSnapshot::diff(&before, &after);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.removed.len(), 2);
+
+        let mut replay = original;
+        replay.apply_diff(&diff);
+        let mut replayed: Vec<i32> = replay.snapshot().points.iter().map(|p| p.data).collect();
+        let mut expected: Vec<i32> = after.points.iter().map(|p| p.data).collect();
+        replayed.sort();
+        expected.sort();
+        assert_eq!(replayed, expected);
+    }
+
+    #[test]
+    fn snapshot_order_ignores_structure() {
+        let mut points = vec![];
+        for i in 0..40 {
+            points.push(Point::new(((i * 37) % 100) as f32, ((i * 53) % 100) as f32, i));
+        }
+
+        let mut a = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        let mut b = Quadtree::new(Qrect::screen_size(100., 100.), 8);
+        for point in &points {
+            a.insert(point);
+        }
+        for point in points.iter().rev() {
+            b.insert(point);
+        }
+        assert_ne!(a.node_count(), b.node_count());
+
+        let a_data: Vec<i32> = a.snapshot().points.iter().map(|p| p.data).collect();
+        let b_data: Vec<i32> = b.snapshot().points.iter().map(|p| p.data).collect();
+        assert_eq!(a_data, b_data);
+    }
+
+    #[test]
+    fn grid_downsample_thins_points() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..20 {
+            for j in 0..20 {
+                qt.insert(&Point::new(i as f32 * 5. + 1., j as f32 * 5. + 1., i * 20 + j));
+            }
+        }
+        let thin = qt.grid_downsample(2);
+        let original = qt.collect();
+        let kept = thin.collect();
+        assert!(kept.len() < original.len());
+        assert!(kept.iter().all(|k| original.iter().any(|o| o.data == k.data && o.x == k.x && o.y == k.y)));
+
+        let range = Qrect::new(30., 60., 20., 15.);
+        let full: Vec<i32> = qt.query_rect(&range).iter().map(|p| p.data).collect();
+        assert!(thin.query_rect(&range).iter().all(|p| full.contains(&p.data)));
+    }
+
+    #[test]
+    fn grid_downsample_handles_deep_tree() {
+        // every point nests one level further down the top left corner
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 1);
+        for i in 1..=80 {
+            qt.insert(&Point::new(i as f32 * 1e-30, i as f32 * 1e-30, i));
+        }
+        assert!(qt.depth() >= 64);
+
+        let thin = qt.grid_downsample(1);
+        assert!(!thin.collect().is_empty());
+        assert!(thin.len() <= qt.len());
+    }
+
+    #[test]
+    fn query_rect_on_undivided_tree() {
+        // capacity is never exceeded so this goes down the linear scan path
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 8);
+        for i in 0..6 {
+            qt.insert(&Point::new(i as f32 * 15., i as f32 * 15., i));
+        }
+        let range = Qrect::new(30., 30., 20., 20.);
+        let found: Vec<i32> = qt.query_rect(&range).iter().map(|p| p.data).collect();
+        let iterated: Vec<i32> = qt.iter_rect(&range).map(|p| p.data).collect();
+        assert_eq!(found, vec![1, 2, 3]);
+        assert_eq!(found, iterated);
+        assert!(qt.query_rect(Qrect::new(500., 500., 10., 10.)).is_empty());
+    }
+
+    #[test]
+    fn query_rect_with_hint_ignores_bad_hints() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..30 {
+            qt.insert(&Point::new((i * 3) as f32, (i * 3) as f32, i));
+        }
+        let range = Qrect::new(40., 40., 20., 20.);
+        let expected: Vec<i32> = qt.query_rect(&range).iter().map(|p| p.data).collect();
+        for hint in [0, 3, 1000] {
+            let found: Vec<i32> = qt.query_rect_with_hint(&range, hint).iter().map(|p| p.data).collect();
+            assert_eq!(found, expected);
+        }
+        assert!(qt.query_rect_with_hint(&range, 1000).capacity() >= 1000);
+    }
+
+    #[test]
+    fn expand_boundary_keeps_points() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..25 {
+            qt.insert(&Point::new((i * 4) as f32, (i * 4) as f32, i));
+        }
+
+        assert!(!qt.expand_boundary(Qrect::screen_size(50., 50.)));
+        assert!(qt.expand_boundary(Qrect::new(50., 50., 100., 100.)));
+        assert_eq!(qt.boundary().w, 100.);
+
+        let mut found: Vec<i32> = qt.query_rect(Qrect::screen_size(100., 100.)).iter().map(|p| p.data).collect();
+        found.sort();
+        assert_eq!(found, (0..25).collect::<Vec<_>>());
+        assert!(qt.insert(&Point::new(-40., 140., 25)));
+
+        // one point per quadrant stays inside its stretched node
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 1);
+        for (x, y) in [(10., 10.), (90., 90.), (10., 90.), (90., 10.), (30., 30.)] {
+            qt.insert(&Point::new(x, y, 0));
+        }
+        let depth = qt.depth();
+        assert!(qt.expand_boundary(Qrect::new(50., 50., 100., 100.)));
+        assert_eq!(qt.depth(), depth);
+        assert_eq!(qt.collect().len(), 5);
+    }
+
+    fn set_ops_fixture() -> (Vec<Point<i32>>, Vec<Point<i32>>) {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..10 {
+            qt.insert(&Point::new((i * 10) as f32, 50., i));
+        }
+        let a = qt.query_rect(Qrect::new(25., 50., 25., 5.));
+        let b = qt.query_rect(Qrect::new(50., 50., 15., 5.));
+        (a, b)
+    }
+
+    fn sorted_data(points: &[Point<i32>]) -> Vec<i32> {
+        let mut data: Vec<i32> = points.iter().map(|p| p.data).collect();
+        data.sort();
+        data
+    }
+
+    #[test]
+    fn union_points_dedups() {
+        let (a, b) = set_ops_fixture();
+        assert_eq!(sorted_data(&union_points(&a, &b)), vec![0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(union_points(&a, &a).len(), a.len());
+    }
+
+    #[test]
+    fn intersect_points_keeps_shared() {
+        let (a, b) = set_ops_fixture();
+        assert_eq!(sorted_data(&intersect_points(&a, &b)), vec![4, 5]);
+    }
+
+    #[test]
+    fn difference_points_drops_shared() {
+        let (a, b) = set_ops_fixture();
+        assert_eq!(sorted_data(&difference_points(&a, &b)), vec![0, 1, 2, 3]);
+        assert_eq!(sorted_data(&difference_points(&b, &a)), vec![6]);
+    }
+
+    #[test]
+    fn vec_from_quadtree() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..12 {
+            qt.insert(&Point::new((i * 8) as f32, (i * 5) as f32, i));
+        }
+        let qt_len = qt.len();
+        let points = Vec::from(qt);
+        assert_eq!(points.len(), qt_len);
+        assert_eq!(sorted_data(&points), (0..12).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn quadtree_from_vec() {
+        let points = vec![
+            Point::new(10., 20., 1),
+            Point::new(30., 5., 2),
+            Point::new(-4., 12., 3),
+        ];
+        let qt: Quadtree<i32> = Quadtree::from(points);
+        assert_eq!(qt.len(), 3);
+        let bounds = qt.bounding_box().unwrap();
+        assert_eq!((bounds.x - bounds.w, bounds.y - bounds.h), (-4., 5.));
+        assert_eq!((bounds.x + bounds.w, bounds.y + bounds.h), (30., 20.));
+        assert_eq!(sorted_data(&qt.query_rect(Qrect::range(20., 12., 12.))), vec![1, 2]);
+    }
+
+    #[test]
+    fn qrect_pad_inset_margin() {
+        let rect = Qrect::new(10., 20., 5., 4.);
+        let padded = rect.pad(2.0, 3.0);
+        assert_eq!((padded.x, padded.y, padded.w, padded.h), (10., 20., 7., 7.));
+
+        let inset = rect.inset(2., 10.);
+        assert_eq!((inset.x, inset.y, inset.w, inset.h), (10., 20., 3., 0.));
+
+        let margin = rect.margin_rect(1., 4., 3., 0.);
+        assert_eq!((margin.x, margin.y, margin.w, margin.h), (12., 21., 7., 6.));
+    }
+
+    #[test]
+    fn nearest_where_skips_failing_points() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        qt.insert(&Point::new(51., 50., -1));
+        qt.insert(&Point::new(55., 50., 2));
+        qt.insert(&Point::new(10., 10., 3));
+        qt.insert(&Point::new(90., 90., 4));
+        qt.insert(&Point::new(40., 40., -5));
+
+        let nearest = qt.nearest_where(50., 50., |p| p.data > 0).unwrap();
+        assert_eq!(nearest.data, 2);
+        assert_eq!(qt.nearest_where(50., 50., |_| true).unwrap().data, -1);
+        assert!(qt.nearest_where(50., 50., |p| p.data > 100).is_none());
+    }
+
+    #[test]
+    fn nearest_with_distance_reports_distance() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        assert!(qt.nearest_with_distance(50., 50.).is_none());
+
+        qt.insert(&Point::new(10., 10., 1));
+        qt.insert(&Point::new(53., 54., 2));
+        qt.insert(&Point::new(90., 20., 3));
+        qt.insert(&Point::new(40., 80., 4));
+
+        let (point, dist) = qt.nearest_with_distance(50., 50.).unwrap();
+        assert_eq!(point.data, 2);
+        assert_eq!(dist, 5.);
+
+        let (point, dist) = qt.nearest_with_distance(10., 10.).unwrap();
+        assert_eq!(point.data, 1);
+        assert_eq!(dist, 0.);
+    }
+
+    #[test]
+    fn coordinate_map_round_trip() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..15 {
+            qt.insert(&Point::new((i % 5 * 20) as f32, (i % 3 * 30) as f32, i));
+        }
+        let original = qt.clone();
+        let qt_len = qt.len();
+
+        let map = qt.into_coordinate_map();
+        assert_eq!(map.values().map(|v| v.len()).sum::<usize>(), qt_len);
+        assert_eq!(map[&(20f32.to_bits(), 30f32.to_bits())].len(), 1);
+
+        let rebuilt = Quadtree::from_coordinate_map(map, Qrect::screen_size(100., 100.), 2);
+        for range in [Qrect::new(30., 30., 20., 20.), Qrect::screen_size(100., 100.), Qrect::range(0., 0., 5.)] {
+            assert_eq!(sorted_data(&rebuilt.query_rect(&range)), sorted_data(&original.query_rect(&range)));
+        }
+    }
+
+    #[test]
+    fn pre_subdivide_makes_uniform_grid() {
+        for depth in 0..4 {
+            let mut qt: Quadtree<i32> = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+            qt.pre_subdivide(depth);
+            assert_eq!(qt.leaf_count(), 4usize.pow(depth as u32));
+            assert_eq!(qt.depth(), depth);
+        }
+    }
+
+    fn polygon_fixture() -> Quadtree<i32> {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        let mut i = 0;
+        for x in (5..100).step_by(10) {
+            for y in (5..100).step_by(10) {
+                qt.insert(&Point::new(x as f32, y as f32, i));
+                i += 1;
+            }
+        }
+        qt
+    }
+
+    #[test]
+    fn query_polygon_l_shape() {
+        let qt = polygon_fixture();
+        // an L covering x 0..20 for the full height and y 80..100 for the full width
+        let l_shape = [(0., 0.), (20., 0.), (20., 80.), (100., 80.), (100., 100.), (0., 100.)];
+        let found = qt.query_polygon_approximate(&l_shape);
+        assert_eq!(found.len(), 10 + 10 + 8 + 8);
+        assert!(found.iter().all(|p| p.x < 20. || p.y > 80.));
+    }
+
+    #[test]
+    fn query_polygon_star() {
+        let qt = polygon_fixture();
+        let mut star = vec![];
+        for k in 0..10 {
+            let angle = k as f32 * std::f32::consts::PI / 5. - std::f32::consts::FRAC_PI_2;
+            let r = if k % 2 == 0 { 48. } else { 20. };
+            star.push((50. + r * angle.cos(), 50. + r * angle.sin()));
+        }
+        let found = qt.query_polygon_approximate(&star);
+        // near the center is inside, the notch between two tips is outside
+        assert!(found.iter().any(|p| p.x == 45. && p.y == 45.));
+        assert!(found.iter().any(|p| p.x == 55. && p.y == 25.));
+        assert!(!found.iter().any(|p| p.x == 85. && p.y == 25.));
+    }
+
+    #[test]
+    fn query_polygon_degenerate() {
+        let qt = polygon_fixture();
+        assert!(qt.query_polygon_approximate(&[]).is_empty());
+        assert!(qt.query_polygon_approximate(&[(0., 0.), (100., 100.)]).is_empty());
+
+        // a bow tie crosses itself at the center, both lobes count as inside
+        let bow_tie = [(0., 0.), (100., 100.), (100., 0.), (0., 100.)];
+        let found = qt.query_polygon_approximate(&bow_tie);
+        assert!(found.iter().any(|p| p.x == 5. && p.y == 45.));
+        assert!(!found.iter().any(|p| p.x == 45. && p.y == 5.));
+
+        // a pentagram drawn as one path leaves its center out under the even-odd rule
+        let pentagram: Vec<(f32, f32)> = [0, 2, 4, 1, 3].iter().map(|&k| {
+            let angle = k as f32 * 2. * std::f32::consts::PI / 5. - std::f32::consts::FRAC_PI_2;
+            (50. + 48. * angle.cos(), 50. + 48. * angle.sin())
+        }).collect();
+        let found = qt.query_polygon_approximate(&pentagram);
+        assert!(!found.iter().any(|p| p.x == 45. && p.y == 55.));
+        assert!(found.iter().any(|p| p.x == 55. && p.y == 25.));
+    }
+
+    #[test]
+    fn query_rect_capped_per_leaf_limits_dense_cells() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        // the first four fill the root, the last lands alone in the bottom right
+        for i in 0..4 {
+            qt.insert(&Point::new(10. + i as f32, 10., i));
+        }
+        qt.insert(&Point::new(90., 90., 4));
+
+        let everything = Qrect::screen_size(100., 100.);
+        assert_eq!(qt.query_rect(&everything).len(), 5);
+        assert_eq!(sorted_data(&qt.query_rect_capped_per_leaf(&everything, 2)), vec![0, 1, 4]);
+        assert_eq!(qt.query_rect_capped_per_leaf(&everything, 10).len(), 5);
+    }
+
+    #[test]
+    fn structure_string_shows_layout() {
+        struct Opaque;
+        impl Clone for Opaque {
+            fn clone(&self) -> Self { Opaque }
+        }
+
+        let mut qt = Quadtree::new(Qrect::new(25., 25., 25., 25.), 3);
+        for i in 0..4 {
+            qt.insert(&Point::new(5. + i as f32, 5., Opaque));
+        }
+        let structure = qt.structure_string();
+        let lines: Vec<&str> = structure.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "[depth=0 boundary=(25,25 ±25) points=3 divided=true]");
+        assert_eq!(lines[1], "  TL: [depth=1 boundary=(12.5,12.5 ±12.5) points=1 divided=false]");
+        assert!(lines[4].starts_with("  BR: [depth=1"));
+
+        let mut written = String::new();
+        qt.write_structure(&mut written).unwrap();
+        assert_eq!(written, structure);
+    }
+
+    #[test]
+    fn count_where_matches_manual_count() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..30 {
+            qt.insert(&Point::new((i * 3) as f32, (i * 2) as f32, i - 12));
+        }
+        let manual = qt.collect().iter().filter(|p| p.data > 0).count();
+        assert_eq!(qt.count_where(|data| *data > 0), manual);
+        assert_eq!(manual, 17);
+    }
+
+    #[test]
+    fn nan_ranges_find_nothing() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..10 {
+            qt.insert(&Point::new((i * 10) as f32, (i * 10) as f32, i));
+        }
+        let bad = Qrect::new(f32::NAN, 50., 50., 50.);
+        assert!(!bad.is_finite());
+        assert!(qt.query_rect(&bad).is_empty());
+        assert!(qt.query_rect(Qrect::new(50., 50., f32::INFINITY, 10.)).is_empty());
+        assert_eq!(qt.iter_rect(&bad).count(), 0);
+        assert!(qt.query_rect_with_hint(&bad, 4).is_empty());
+        assert!(qt.query_rect_capped_per_leaf(&bad, 4).is_empty());
+        assert!(qt.query_circle(50., f32::NAN, 20.).is_empty());
+        assert!(qt.query_circle(50., 50., f32::NAN).is_empty());
+    }
+
+    #[test]
+    fn query_spiral_covers_circle_once() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        let mut i = 0;
+        for x in (0..100).step_by(7) {
+            for y in (0..100).step_by(9) {
+                qt.insert(&Point::new(x as f32, y as f32, i));
+                i += 1;
+            }
+        }
+        let rings = qt.query_spiral(50., 50., 0., 40., 4);
+        assert_eq!(rings.len(), 4);
+
+        let mut spiral: Vec<i32> = rings.iter().flatten().map(|p| p.data).collect();
+        spiral.sort();
+        assert_eq!(spiral, sorted_data(&qt.query_circle(50., 50., 40.)));
+
+        assert!(!rings[0].is_empty());
+        assert!(rings[0].iter().all(|p| ((p.x - 50.).powi(2) + (p.y - 50.).powi(2)).sqrt() < 10.));
+        assert!(rings[3].iter().all(|p| ((p.x - 50.).powi(2) + (p.y - 50.).powi(2)).sqrt() >= 30.));
+    }
+
+    #[test]
+    fn rebuilt_with_capacity_leaves_original() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..20 {
+            qt.insert(&Point::new((i * 5) as f32, (i * 4) as f32, i));
+        }
+        let rebuilt = qt.rebuilt_with_capacity(8);
+        assert_eq!(rebuilt.capacity(), 8);
+        assert_eq!(qt.capacity(), 2);
+        assert_eq!(sorted_data(&rebuilt.collect()), sorted_data(&qt.collect()));
+        assert!(rebuilt.depth() < qt.depth());
+    }
+
+    #[test]
+    fn intersects_any_point_and_circle() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..10 {
+            qt.insert(&Point::new(5. + i as f32, 5. + i as f32, i));
+        }
+        qt.insert(&Point::new(90., 90., 10));
+
+        assert!(qt.intersects_any_point(&Qrect::range(90., 90., 1.)));
+        assert!(!qt.intersects_any_point(&Qrect::range(50., 50., 20.)));
+        assert!(qt.intersects_any_point(&Qrect::range(12., 12., 0.5)));
+
+        assert!(qt.intersects_any_circle(85., 85., 8.));
+        assert!(!qt.intersects_any_circle(85., 85., 7.));
+        assert!(!qt.intersects_any_circle(50., 50., 30.));
+        assert!(!Quadtree::<i32>::new(Qrect::screen_size(100., 100.), 2).intersects_any_circle(50., 50., 100.));
+    }
+
+    #[test]
+    fn collect_by_axis_ranges() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        for i in 0..40 {
+            qt.insert(&Point::new((i * 7 % 100) as f32, (i * 13 % 100) as f32, i));
+        }
+        let all = qt.collect();
+
+        let by_x: Vec<Point<i32>> = all.iter().filter(|p| p.x >= 20. && p.x <= 45.).cloned().collect();
+        assert_eq!(sorted_data(&qt.collect_by_x_range(20., 45.)), sorted_data(&by_x));
+
+        let by_y: Vec<Point<i32>> = all.iter().filter(|p| p.y >= 60. && p.y <= 61.).cloned().collect();
+        assert_eq!(sorted_data(&qt.collect_by_y_range(60., 61.)), sorted_data(&by_y));
+    }
+
+    #[test]
+    fn quantization_makes_queries_deterministic() {
+        let inputs_a = [(10.1, 30.), (30.26, 40.), (49.99, 49.99)];
+        let inputs_b = [(10.12, 30.), (30.28, 40.04), (50.01, 49.99)];
+        let range = Qrect::from_min_max(10.11, 20., 50., 50.);
+
+        let build = |inputs: &[(f32, f32)], quantum: Option<f32>| {
+            let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 1);
+            if let Some(quantum) = quantum {
+                qt = qt.with_quantization(quantum);
+            }
+            for (i, &(x, y)) in inputs.iter().enumerate() {
+                qt.insert(&Point::new(x, y, i));
+            }
+            qt
+        };
+
+        // without snapping the tiny differences change which points are found
+        let raw_a: Vec<usize> = build(&inputs_a, None).query_rect(&range).iter().map(|p| p.data).collect();
+        let raw_b: Vec<usize> = build(&inputs_b, None).query_rect(&range).iter().map(|p| p.data).collect();
+        assert_ne!(raw_a, raw_b);
+
+        let a = build(&inputs_a, Some(0.5)).query_rect(&range);
+        let b = build(&inputs_b, Some(0.5)).query_rect(&range);
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 2);
+        assert_eq!((a[0].x, a[0].y), (30.5, 40.));
+    }
+
+    #[test]
+    #[should_panic(expected = "quantum must be finite and positive")]
+    fn quantization_rejects_zero_quantum() {
+        let _ = Quadtree::<()>::new(Qrect::screen_size(100., 100.), 1).with_quantization(0.);
+    }
+
+    #[test]
+    fn structural_check_through_operations() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        assert!(qt.structural_check());
+        assert_eq!(qt.count_nodes_at_depth(0), 1);
+        assert_eq!(qt.count_nodes_at_depth(1), 0);
+
+        for i in 0..30 {
+            qt.insert(&Point::new((i * 3) as f32, (i * 3) as f32, i));
+            assert!(qt.structural_check());
+        }
+        assert_eq!(qt.count_nodes_at_depth(1), 4);
+
+        for i in (0..30).step_by(2) {
+            qt.remove(&Point::new((i * 3) as f32, (i * 3) as f32, i));
+            assert!(qt.structural_check());
+        }
+
+        qt.pre_subdivide(3);
+        assert!(qt.structural_check());
+        assert_eq!(qt.count_nodes_at_depth(3), 64);
+
+        assert!(qt.expand_boundary(Qrect::new(50., 50., 80., 80.)));
+        assert!(qt.structural_check());
+        qt.merge_nearby_points(5.);
+        assert!(qt.structural_check());
+        qt.empty();
+        assert!(qt.structural_check());
+        assert_eq!(qt.node_count(), 1);
+    }
+
+    #[test]
+    fn structural_check_catches_corruption() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..20 {
+            qt.insert(&Point::new((i * 5) as f32, (i * 4) as f32, i));
+        }
+        assert!(qt.structural_check());
+
+        let mut wrong_count = qt.clone();
+        wrong_count.top_left.as_mut().unwrap().count += 1;
+        assert!(!wrong_count.structural_check());
+
+        let mut outside = qt.clone();
+        outside.top_left.as_mut().unwrap().points.push(Point::new(90., 90., 99));
+        outside.top_left.as_mut().unwrap().count += 1;
+        outside.count += 1;
+        assert!(!outside.structural_check());
+
+        let mut missing_child = qt.clone();
+        missing_child.bottom_right = None;
+        assert!(!missing_child.structural_check());
+    }
+
+    #[test]
+    fn leaves_match_leaf_count() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..40 {
+            qt.insert(&Point::new((i * 7 % 100) as f32, (i * 11 % 100) as f32, i));
+        }
+        assert_eq!(qt.leaves().count(), qt.leaf_count());
+        assert!(qt.leaves().all(|leaf| leaf.leaf_count() == 1));
+
+        // leaf points plus the points held by divided nodes make up the whole tree
+        let mut combined: Vec<Point<i32>> = qt.leaves().flat_map(|leaf| leaf.points().to_vec()).collect();
+        let leaf_points = combined.len();
+        let mut stack = vec![&qt];
+        while let Some(node) = stack.pop() {
+            if node.divided {
+                combined.extend(node.points().to_vec());
+                stack.extend(node.children());
+            }
+        }
+        assert!(leaf_points > 0);
+        assert_eq!(sorted_data(&combined), sorted_data(&qt.collect()));
+    }
+
+    #[test]
+    fn encode_position_round_trips() {
+        let qt: Quadtree<i32> = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        let mut codes = HashSet::new();
+        for x in 0..=100 {
+            for y in 0..=100 {
+                let (x, y) = (x as f32, y as f32);
+                let code = qt.encode_position(x, y);
+                assert!(codes.insert(code));
+                let (dx, dy) = qt.decode_position(code);
+                assert!((dx - x).abs() < 0.001 && (dy - y).abs() < 0.001);
+            }
+        }
+        assert_eq!(qt.encode_position(0., 0.), 0);
+        assert_eq!(qt.encode_position(100., 0.) >> 32, u32::MAX as u64);
+    }
+
+    #[test]
+    fn any_in_rect_multi_checks_every_layer() {
+        let mut ground = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        let mut air = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        ground.insert(&Point::new(10., 10., 0));
+        air.insert(&Point::new(60., 60., 1));
+
+        assert!(any_in_rect_multi([&ground, &air], &Qrect::range(60., 60., 2.)));
+        assert!(!any_in_rect_multi([&ground, &air], &Qrect::range(30., 30., 2.)));
+        assert!(!any_in_rect_multi(Vec::<&Quadtree<i32>>::new(), &Qrect::range(60., 60., 2.)));
+    }
+
+    // small deterministic generator so tests don't need a rand dependency
+    fn lcg(seed: &mut u32) -> f32 {
+        *seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        (*seed >> 8) as f32 / (1 << 24) as f32
+    }
+
+    #[test]
+    fn find_nearest_pair_matches_brute_force() {
+        let empty: Quadtree<usize> = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        assert!(empty.find_nearest_pair().is_none());
+
+        let mut seed = 7;
+        for n in [2, 10, 100, 1000] {
+            let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+            let mut points = vec![];
+            for i in 0..n {
+                let point = Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i);
+                qt.insert(&point);
+                points.push(point);
+            }
+
+            let mut brute = f32::MAX;
+            for i in 0..n {
+                for j in i + 1..n {
+                    brute = brute.min(((points[i].x - points[j].x).powi(2) + (points[i].y - points[j].y).powi(2)).sqrt());
+                }
+            }
+
+            let (a, b, dist) = qt.find_nearest_pair().unwrap();
+            assert_ne!(a.data, b.data);
+            assert!((dist - brute).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn insert_non_overlapping_nudges_points() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        assert_eq!(qt.insert_non_overlapping(&Point::new(50., 50., 0), 2.), Some((50., 50.)));
+        let (x, y) = qt.insert_non_overlapping(&Point::new(50., 50., 1), 2.).unwrap();
+        assert!((((x - 50.).powi(2) + (y - 50.).powi(2)).sqrt() - 2.).abs() < 1e-4);
+
+        let (x, y) = qt.insert_non_overlapping(&Point::new(51., 50.5, 2), 2.).unwrap();
+        for point in qt.collect().iter().filter(|p| p.data != 2) {
+            assert!(((point.x - x).powi(2) + (point.y - y).powi(2)).sqrt() >= 2. - 1e-4);
+        }
+        assert_eq!(qt.len(), 3);
+
+        // pushed past the right edge so nothing is inserted
+        qt.insert(&Point::new(98.5, 20., 3));
+        assert_eq!(qt.insert_non_overlapping(&Point::new(99.5, 20., 4), 2.), None);
+        assert_eq!(qt.len(), 4);
+    }
+
+    #[test]
+    fn insert_non_overlapping_snaps_before_checking() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4).with_quantization(1.);
+        assert_eq!(qt.insert_non_overlapping(&Point::new(20.4, 20.4, 0), 0.3), Some((20., 20.)));
+        qt.insert(&Point::new(10., 10., 1));
+
+        // a 0.3 push would snap straight back onto (10, 10) so it rounds away instead
+        assert_eq!(qt.insert_non_overlapping(&Point::new(10., 10., 2), 0.3), Some((11., 10.)));
+        let stored = qt.collect().into_iter().find(|p| p.data == 2).unwrap();
+        assert_eq!((stored.x, stored.y), (11., 10.));
+    }
+
+    #[test]
+    fn insert_non_overlapping_gives_up_when_crowded() {
+        let mut qt = Quadtree::new(Qrect::screen_size(20., 20.), 4);
+        for i in 0..=20 {
+            for j in 0..=20 {
+                qt.insert(&Point::new(i as f32, j as f32, 0));
+            }
+        }
+        let len = qt.len();
+        assert_eq!(qt.insert_non_overlapping(&Point::new(10.2, 10.3, 1), 3.), None);
+        assert_eq!(qt.len(), len);
+    }
+
+    #[test]
+    fn moving_window_collect_returns_nearest() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        let mut seed = 11;
+        for i in 0..1000 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let dist = |p: &Point<i32>| ((p.x - 40.).powi(2) + (p.y - 60.).powi(2)).sqrt();
+
+        let window = qt.moving_window_collect(40., 60., 15., 10);
+        assert_eq!(window.len(), 10);
+        assert!(window.windows(2).all(|pair| dist(&pair[0]) <= dist(&pair[1])));
+
+        let mut truth = qt.query_circle(40., 60., 15.);
+        truth.sort_by(|a, b| dist(a).total_cmp(&dist(b)));
+        let nearest: Vec<i32> = truth.iter().take(10).map(|p| p.data).collect();
+        assert!(window.iter().all(|p| nearest.contains(&p.data)));
+
+        // a tiny radius caps the result below max_points
+        assert_eq!(qt.moving_window_collect(40., 60., 0.5, 10).len(), qt.query_circle(40., 60., 0.5).len());
+    }
+
+    #[test]
+    fn write_rect_csv_streams_rows() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        qt.insert(&Point::new(10., 10., "a"));
+        qt.insert(&Point::new(12.5, 14., "b"));
+        qt.insert(&Point::new(80., 80., "c"));
+
+        let mut buffer: Vec<u8> = vec![];
+        qt.write_rect_csv(&Qrect::range(10., 10., 5.), &mut buffer).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), "10,10,a\n12.5,14,b\n");
+    }
+
+    #[test]
+    fn remove_all_removes_batch() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        let mut points = vec![];
+        for i in 0..40 {
+            let point = Point::new((i * 7 % 100) as f32, (i * 3 % 100) as f32, i);
+            qt.insert(&point);
+            points.push(point);
+        }
+        let dead: Vec<Point<i32>> = points.iter().filter(|p| p.data % 2 == 0).cloned().collect();
+        assert_eq!(qt.remove_all(&dead), 20);
+        assert_eq!(qt.remove_all(&dead), 0);
+        assert_eq!(sorted_data(&qt.collect()), (1..40).step_by(2).collect::<Vec<_>>());
+        assert!(qt.structural_check());
+
+        assert_eq!(qt.remove_all(&qt.collect()), 20);
+        assert_eq!(qt.node_count(), 1);
+    }
+
+    #[test]
+    fn entry_list_round_trip() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..12 {
+            qt.insert(&Point::new(i as f32 * 8.3, 100. - i as f32 * 6.1, i));
+        }
+        let entries = qt.to_entry_list();
+        assert_eq!(entries.len(), qt.len());
+        assert!(entries.iter().all(|(aabb, _)| aabb[0] == aabb[2] && aabb[1] == aabb[3]));
+
+        let rebuilt = Quadtree::from_aabb_list(entries, 2);
+        assert_eq!(rebuilt.len(), qt.len());
+        for point in rebuilt.collect() {
+            assert!((point.x - point.data as f32 * 8.3).abs() < 1e-4);
+            assert!((point.y - (100. - point.data as f32 * 6.1)).abs() < 1e-4);
+        }
+
+        let boxed = Quadtree::from_aabb_list(vec![([0., 0., 4., 2.], 'a')], 4);
+        assert_eq!((boxed.collect()[0].x, boxed.collect()[0].y), (2., 1.));
+    }
+
+    #[test]
+    fn adaptive_insert_bounds_depth() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        // the stack stays in the root, rebuilding couldn't make it any shallower
+        for i in 0..1000 {
+            assert_eq!(qt.adaptive_insert(&Point::new(50., 50., i)), InsertResult::Inserted { depth: 0 });
+        }
+        assert!(qt.depth() <= 1);
+        assert_eq!(qt.capacity(), 4);
+        assert_eq!(qt.len(), 1000);
+        assert_eq!(qt.query_rect(Qrect::range(50., 50., 0.1)).len(), 1000);
+
+        // spread out points still subdivide as usual
+        assert_eq!(qt.adaptive_insert(&Point::new(10., 10., 1000)), InsertResult::Inserted { depth: 1 });
+        assert_eq!(qt.adaptive_insert(&Point::new(500., 10., 0)), InsertResult::Rejected { reason: InsertRejection::OutOfBounds });
+        assert_eq!(qt.adaptive_insert(&Point::new(f32::NAN, 10., 0)), InsertResult::Rejected { reason: InsertRejection::NonFinite });
+        assert!(qt.structural_check());
+    }
+
+    #[test]
+    fn adaptive_insert_matches_insert_settings() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4).with_quantization(1.);
+        assert_eq!(qt.adaptive_insert(&Point::new(20.4, 20.4, 0)), InsertResult::Inserted { depth: 0 });
+        assert_eq!((qt.collect()[0].x, qt.collect()[0].y), (20., 20.));
+
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 1).with_auto_rebalance(2.);
+        // each close point lands a level deeper until the depth passes twice the ideal
+        let results: Vec<InsertResult> = (0..6)
+            .map(|i| qt.adaptive_insert(&Point::new(50. + i as f32 * 0.01, 50., i)))
+            .collect();
+        assert!(results.iter().any(|result| matches!(result, InsertResult::Rebalanced { .. })), "{results:?}");
+        assert_eq!(qt.len(), 6);
+    }
+
+    #[cfg(feature = "bitvec")]
+    #[test]
+    fn query_rect_mask_matches_indices() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..30 {
+            qt.insert(&Point::new((i * 7 % 100) as f32, (i * 3) as f32, i));
+        }
+        let range = Qrect::new(40., 40., 25., 25.);
+        let mask = qt.query_rect_mask(&range, 30);
+        let set: Vec<usize> = mask.iter_ones().collect();
+        let mut indices: Vec<usize> = qt.query_rect(&range).iter().map(|p| p.data).collect();
+        indices.sort();
+        assert_eq!(set, indices);
+    }
+
+    #[test]
+    fn bulk_load_matches_insert() {
+        let mut seed = 3;
+        let mut points = vec![];
+        for i in 0..2000 {
+            points.push(Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        points.push(Point::new(50., 50., 2000));
+        points.push(Point::new(500., 50., 2001));
+
+        let mut inserted = Quadtree::new(Qrect::screen_size(100., 100.), 8);
+        for point in &points {
+            inserted.insert(point);
+        }
+        let loaded = Quadtree::bulk_load(points, Qrect::screen_size(100., 100.), 8);
+
+        assert_eq!(loaded.validate(), Ok(()));
+        assert_eq!(inserted.validate(), Ok(()));
+        assert_eq!(loaded.len(), 2001);
+        assert_eq!(sorted_data(&loaded.collect()), sorted_data(&inserted.collect()));
+        for range in [Qrect::new(30., 30., 10., 20.), Qrect::range(50., 50., 0.), Qrect::new(90., 10., 15., 15.)] {
+            assert_eq!(sorted_data(&loaded.query_rect(&range)), sorted_data(&inserted.query_rect(&range)));
+        }
+        // points only live in leaves so the leaves carry everything
+        assert_eq!(loaded.leaves().map(|leaf| leaf.points().len()).sum::<usize>(), 2001);
+    }
+
+    #[test]
+    fn bulk_load_tiles_follow_the_data() {
+        // everything crammed into one corner, a center split would need many levels to reach it
+        let mut seed = 8;
+        let points: Vec<Point<usize>> = (0..1024).map(|i| Point::new(lcg(&mut seed), lcg(&mut seed), i)).collect();
+        let qt = Quadtree::bulk_load(points.clone(), Qrect::screen_size(100., 100.), 4);
+        assert_eq!(qt.validate(), Ok(()));
+        assert_eq!(qt.depth(), 4);
+        assert_eq!(qt.leaf_point_distribution(), BTreeMap::from([(4, 256)]));
+
+        // inserts and removes still find their way through the off center cells
+        let mut qt = qt;
+        assert!(qt.insert(&Point::new(0.5, 0.5, 1024)));
+        assert!(qt.remove(&points[100]));
+        assert_eq!(qt.len(), 1024);
+        assert_eq!(qt.validate(), Ok(()));
+
+        // points a hair apart are split by their medians instead of halving down to them
+        let tiny = vec![Point::new(0., 0., 0), Point::new(1e-30, 0., 1), Point::new(2e-30, 0., 2)];
+        let qt = Quadtree::bulk_load(tiny, Qrect::screen_size(100., 100.), 1);
+        assert!(qt.depth() <= 2, "{}", qt.depth());
+        assert_eq!(qt.len(), 3);
+        assert!(qt.depth() <= BULK_LOAD_MAX_DEPTH);
+    }
+
+    #[test]
+    fn query_rect_spiral_orders_bands() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        let mut seed = 5;
+        for i in 0..300 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let range = Qrect::new(50., 50., 40., 40.);
+        let found = qt.query_rect_spiral(&range, (50., 50.));
+        assert_eq!(sorted_data(&found), sorted_data(&qt.query_rect(&range)));
+
+        let band = |p: &Point<i32>| (((p.x - 50.).powi(2) + (p.y - 50.).powi(2)).sqrt() / 5.) as usize;
+        assert!(found.windows(2).all(|pair| band(&pair[0]) <= band(&pair[1])));
+        assert!(band(&found[0]) < band(&found[found.len() - 1]));
+    }
+
+    #[test]
+    fn merge_aligned_matches_pointwise_merge() {
+        let mut seed = 9;
+        let mut a = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        let mut b = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        for i in 0..200 {
+            let point = Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i);
+            if i % 3 == 0 { a.insert(&point); } else { b.insert(&point); }
+        }
+
+        let mut pointwise = a.clone();
+        for point in b.collect() {
+            pointwise.insert(&point);
+        }
+        let mut aligned = a.clone();
+        aligned.merge_aligned(&b);
+
+        assert_eq!(aligned.validate(), Ok(()));
+        assert_eq!(sorted_data(&aligned.collect()), sorted_data(&pointwise.collect()));
+        let range = Qrect::new(60., 30., 20., 25.);
+        assert_eq!(sorted_data(&aligned.query_rect(&range)), sorted_data(&pointwise.query_rect(&range)));
+        assert!(aligned.points().len() <= aligned.capacity());
+
+        // mismatched capacity falls back to inserting point by point
+        let mut other = Quadtree::new(Qrect::screen_size(100., 100.), 5);
+        other.insert(&Point::new(1., 1., 500));
+        aligned.merge_aligned(&other);
+        assert_eq!(aligned.len(), 201);
+
+        // a bulk loaded tree's off center cells don't pair up with insert's
+        let loaded = Quadtree::bulk_load(b.collect(), Qrect::screen_size(100., 100.), 3);
+        let mut merged = a.clone();
+        merged.merge_aligned(&loaded);
+        assert_eq!(merged.validate(), Ok(()));
+        assert_eq!(sorted_data(&merged.collect()), sorted_data(&pointwise.collect()));
+
+        // a quantized tree snaps what it merges
+        let mut snapped = Quadtree::new(Qrect::screen_size(100., 100.), 3).with_quantization(1.);
+        let mut raw = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        raw.insert(&Point::new(10.3, 20.6, 0));
+        snapped.merge_aligned(&raw);
+        assert_eq!((snapped.collect()[0].x, snapped.collect()[0].y), (10., 21.));
+    }
+
+    #[test]
+    fn query_rect_bounded_respects_deadline() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        let mut seed = 21;
+        for i in 0..2000 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let range = Qrect::new(40., 60., 35., 30.);
+        let full = sorted_data(&qt.query_rect(&range));
+
+        let (partial, finished) = qt.query_rect_bounded(&range, std::time::Instant::now());
+        assert!(!finished);
+        assert!(partial.iter().all(|p| full.contains(&p.data)));
+
+        let later = std::time::Instant::now() + std::time::Duration::from_secs(60);
+        let (all, finished) = qt.query_rect_bounded(&range, later);
+        assert!(finished);
+        assert_eq!(sorted_data(&all), full);
+    }
+
+    #[test]
+    fn occupancy_ratio_in_expected_range() {
+        let empty: Quadtree<i32> = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        assert_eq!(empty.occupancy_ratio(), 0.);
+
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 8);
+        let mut seed = 17;
+        for i in 0..8 * 200 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let ratio = qt.occupancy_ratio();
+        assert!(ratio > 0.3 && ratio <= 1.0, "ratio {}", ratio);
+    }
+
+    #[test]
+    fn cached_query_reuses_until_region_changes() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..20 {
+            qt.insert(&Point::new((i * 5) as f32, (i * 5) as f32, i));
+        }
+        let range = Qrect::new(25., 25., 10., 10.);
+        let mut cache = CachedQuery::new();
+
+        let first = cache.get(&qt, &range).clone();
+        assert_eq!(cache.hits, 0);
+        assert_eq!(cache.get(&qt, &range), &first);
+        assert_eq!(cache.hits, 1);
+
+        // a change far from the range leaves the cache alone
+        qt.insert(&Point::new(95., 5., 100));
+        cache.get(&qt, &range);
+        assert_eq!(cache.hits, 2);
+
+        qt.insert(&Point::new(24., 26., 101));
+        let updated = cache.get(&qt, &range).clone();
+        assert_eq!(cache.hits, 2);
+        assert_eq!(updated.len(), first.len() + 1);
+
+        cache.get(&qt, &Qrect::new(25., 25., 11., 11.));
+        assert_eq!(cache.hits, 2);
+
+        // stamps handed out on another thread still invalidate the cache
+        let range = Qrect::new(25., 25., 11., 11.);
+        cache.get(&qt, &range);
+        assert_eq!(cache.hits, 3);
+        std::thread::scope(|scope| {
+            scope.spawn(|| qt.insert(&Point::new(26., 24., 102)));
+        });
+        assert_eq!(cache.get(&qt, &range).len(), updated.len() + 1);
+        assert_eq!(cache.hits, 3);
+    }
+
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    // counts are per thread so tests running in parallel don't disturb each other
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(|count| count.get())
+    }
+
+    #[test]
+    fn fold_in_circle_matches_query_circle() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        let mut seed = 31;
+        for i in 0..500 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let truth: f32 = qt.query_circle(40., 55., 20.).iter().map(|p| p.x).sum::<f32>();
+        let truth_count = qt.query_circle(40., 55., 20.).len();
+
+        let before = allocations();
+        let sum = qt.fold_in_circle(40., 55., 20., 0., |acc, p| acc + p.x);
+        let count = qt.fold_in_circle_count(40., 55., 20.);
+        let rect_count = qt.fold_in_rect(&Qrect::new(40., 55., 20., 20.), 0, |acc, _| acc + 1);
+        assert_eq!(allocations(), before);
+        let _ = qt.query_circle(40., 55., 20.);
+        assert!(allocations() > before);
+
+        assert!((sum - truth).abs() < 1e-2);
+        assert_eq!(count, truth_count);
+        assert_eq!(rect_count, qt.query_rect(Qrect::new(40., 55., 20., 20.)).len());
+    }
+
+    #[test]
+    fn layered_points_query_per_layer() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..10 {
+            qt.insert_layered(&Point::new((i * 10) as f32, 50., i), 0);
+            qt.insert_layered(&Point::new((i * 10) as f32, 52., i + 100), 1);
+        }
+        let range = Qrect::new(25., 50., 20., 5.);
+        assert_eq!(sorted_data(&qt.query_rect_layer(&range, 0)), vec![1, 2, 3, 4]);
+        assert_eq!(sorted_data(&qt.query_rect_layer(&range, 1)), vec![101, 102, 103, 104]);
+        assert!(qt.query_rect_layer(&range, 2).is_empty());
+        assert_eq!(qt.query_rect(&range).len(), 8);
+        assert!(qt.query_rect_layer(&range, 1).iter().all(|p| p.layer() == 1));
+
+        // the layer doesn't take part in matching so remove still finds the point
+        assert_eq!(Point::new(10., 52., 101), qt.query_rect_layer(&range, 1)[0]);
+        assert!(qt.remove(&Point::new(10., 52., 101)));
+        assert_eq!(qt.query_rect_layer(&range, 1).len(), 3);
+    }
+
+    #[test]
+    fn group_by_distance_buckets_bands_points() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        let mut seed = 41;
+        for i in 0..400 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let dist = |p: &Point<i32>| ((p.x - 30.).powi(2) + (p.y - 70.).powi(2)).sqrt();
+
+        let buckets = [5., 20., 50.];
+        let groups = qt.group_by_distance_buckets(30., 70., &buckets);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups.iter().map(|g| g.len()).sum::<usize>(), qt.query_circle(30., 70., 50.).len());
+        for (i, group) in groups.iter().enumerate() {
+            let low = if i == 0 { 0. } else { buckets[i - 1] };
+            assert!(group.iter().all(|p| dist(p) >= low && dist(p) < buckets[i]));
+        }
+
+        let everything = qt.group_by_distance_buckets(30., 70., &[5., 20., 50., f32::INFINITY]);
+        assert_eq!(everything.iter().map(|g| g.len()).sum::<usize>(), qt.len());
+        assert!(everything[3].iter().all(|p| dist(p) >= 50.));
+    }
+
+    #[test]
+    fn query_rect_timed_completes_with_room() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        for i in 0..200 {
+            qt.insert(&Point::new((i % 20 * 5) as f32, (i / 20 * 10) as f32, i));
+        }
+        let range = Qrect::new(30., 40., 20., 25.);
+        let (found, finished) = qt.query_rect_timed(&range, std::time::Duration::from_secs(60));
+        assert!(finished);
+        assert_eq!(sorted_data(&found), sorted_data(&qt.query_rect(&range)));
+
+        let (_, finished) = qt.query_rect_timed(&range, std::time::Duration::ZERO);
+        assert!(!finished);
+    }
+
+    #[test]
+    fn quadrant_count_tree_totals() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        let mut seed = 51;
+        for i in 0..150 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let counts = qt.quadrant_count_tree();
+        assert_eq!(counts.total(), qt.len());
+        assert_eq!(counts.count, 2);
+
+        let mut stack = vec![(&counts, &qt)];
+        while let Some((counts, node)) = stack.pop() {
+            assert_eq!(counts.count, node.points().len());
+            match &counts.children {
+                None => assert_eq!(node.leaf_count(), 1),
+                Some(children) => stack.extend(children.iter().zip(node.children())),
+            }
+        }
+    }
+
+    #[test]
+    fn query_circle_sq_matches_query_circle() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        let mut seed = 61;
+        for i in 0..300 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        for (x, y, r) in [(50., 50., 10.), (0., 100., 30.), (75., 20., 0.5)] {
+            assert_eq!(qt.query_circle_sq(x, y, r * r), qt.query_circle(x, y, r));
+        }
+    }
+
+    #[test]
+    fn query_rect_with_exclusions_skips_zones() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        let mut seed = 71;
+        for i in 0..500 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let range = Qrect::new(50., 50., 40., 40.);
+        let zone_a = Qrect::new(25., 25., 12.5, 12.5);
+        let zone_b = Qrect::new(70., 60., 10., 20.);
+
+        assert_eq!(qt.query_rect_with_exclusions(&range, &[]), qt.query_rect(&range));
+        assert_eq!(
+            sorted_data(&qt.query_rect_with_exclusions(&range, std::slice::from_ref(&zone_a))),
+            sorted_data(&qt.query_excluding_rect(&range, &zone_a))
+        );
+
+        let found = qt.query_rect_with_exclusions(&range, &[zone_a.clone(), zone_b.clone()]);
+        assert!(found.iter().all(|p| !zone_a.contains_point(p) && !zone_b.contains_point(p)));
+        let expected = qt.query_rect(&range).iter().filter(|p| !zone_a.contains_point(p) && !zone_b.contains_point(p)).count();
+        assert_eq!(found.len(), expected);
+        assert!(found.len() < qt.query_excluding_rect(&range, &zone_a).len());
+    }
+
+    #[test]
+    fn query_rect_with_depth_reports_density() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        qt.insert(&Point::new(90., 90., 0));
+        qt.insert(&Point::new(80., 80., 1));
+        for i in 2..20 {
+            qt.insert(&Point::new(10. + i as f32 * 0.5, 10. + i as f32 * 0.5, i));
+        }
+        let found = qt.query_rect_with_depth(&Qrect::screen_size(100., 100.));
+        assert_eq!(found.len(), 20);
+
+        let root_depth = found.iter().find(|(p, _)| p.data == 0).unwrap().1;
+        let dense_depth = found.iter().find(|(p, _)| p.data == 19).unwrap().1;
+        assert_eq!(root_depth, 0);
+        assert!(dense_depth > root_depth);
+    }
+
+    #[test]
+    fn all_points_satisfy_and_friends() {
+        let empty: Quadtree<i32> = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        assert!(empty.all_points_satisfy(|_| true));
+        assert!(empty.all_points_satisfy(|_| false));
+        assert!(empty.none_satisfy(|_| true));
+        assert_eq!(empty.count_satisfying(|_| true), 0);
+
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..30 {
+            qt.insert(&Point::new((i * 3) as f32, (i * 2) as f32, i));
+        }
+        assert!(qt.all_points_satisfy(|_| true));
+        assert!(!qt.all_points_satisfy(|_| false));
+        assert!(qt.all_points_satisfy(|p| p.x >= 0.));
+        assert!(!qt.all_points_satisfy(|p| p.data < 29));
+        assert!(qt.none_satisfy(|p| p.data > 100));
+        assert!(!qt.none_satisfy(|p| p.data == 7));
+        assert_eq!(qt.count_satisfying(|p| p.x < 30.), 10);
+        assert_eq!(qt.iter().count(), 30);
+    }
+
+    #[cfg(feature = "simd")]
+    #[test]
+    fn simd_filter_matches_scalar() {
+        let mut seed = 11;
+        let mut points = vec![];
+        for i in 0..101 {
+            points.push(Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        // edges of the range are inclusive on both paths
+        points.push(Point::new(25., 40., 101));
+        points.push(Point::new(75., 60., 102));
+
+        let range = Qrect::new(50., 50., 25., 10.);
+        let mut scalar = vec![];
+        let mut simd = vec![];
+        filter_in_rect_scalar(&points, &range, &mut scalar);
+        filter_in_rect_simd(&points, &range, &mut simd);
+        assert_eq!(simd, scalar);
+        assert!(scalar.iter().any(|p| p.data == 101) && scalar.iter().any(|p| p.data == 102));
+
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 200);
+        for point in &points {
+            qt.insert(point);
+        }
+        assert_eq!(qt.query_rect(&range), scalar);
+    }
+
+    #[test]
+    fn distance_to_boundary_signs() {
+        let qt: Quadtree<i32> = Quadtree::new(Qrect::screen_size(100., 60.), 4);
+        assert_eq!(qt.distance_to_boundary(50., 30.), 30.);
+        assert_eq!(qt.distance_to_boundary(98., 30.), 2.);
+        assert_eq!(qt.distance_to_boundary(100., 30.), 0.);
+        assert_eq!(qt.distance_to_boundary(110., 30.), -10.);
+        // past a corner the distance is to the corner itself
+        assert_eq!(qt.distance_to_boundary(103., 64.), -5.);
+    }
+
+    #[test]
+    fn unique_positions_dedup_shared_coordinates() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..10 {
+            qt.insert(&Point::new(30., 30., i));
+        }
+        for i in 0..10 {
+            qt.insert(&Point::new(i as f32 * 9. + 1., 80., 10 + i));
+        }
+        assert_eq!(qt.len(), 20);
+
+        let mut positions = qt.collect_unique_positions();
+        assert_eq!(positions.len(), 11);
+        assert_eq!(qt.count_unique_positions(), 11);
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!(positions.contains(&(30., 30.)));
+        assert_eq!(positions[0], (1., 80.));
+    }
+
+    #[test]
+    fn query_obb_diamond() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        let mut id = 0;
+        for y in 0..=20 {
+            for x in 0..=20 {
+                qt.insert(&Point::new(x as f32 * 5., y as f32 * 5., id));
+                id += 1;
+            }
+        }
+
+        // a square turned 45 degrees is a diamond, |dx| + |dy| <= half * sqrt(2), sized to miss the grid
+        let half = 17.5 / 2f32.sqrt();
+        let found = qt.query_obb(50., 50., half, half, std::f32::consts::FRAC_PI_4);
+        let mut expected: Vec<i32> = qt.collect().into_iter()
+            .filter(|p| (p.x - 50.).abs() + (p.y - 50.).abs() <= 17.5)
+            .map(|p| p.data)
+            .collect();
+        expected.sort();
+        assert_eq!(sorted_data(&found), expected);
+        assert!(found.iter().any(|p| p.x == 65. && p.y == 50.));
+        assert!(!found.iter().any(|p| p.x == 60. && p.y == 60.));
+        assert!(!found.iter().any(|p| p.x == 70. && p.y == 50.));
+    }
+
+    #[test]
+    fn query_rect_no_duplicates_on_shared_corner() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 1);
+        qt.insert(&Point::new(10., 10., 0));
+        // sits on the corner shared by all four children once the root splits
+        qt.insert(&Point::new(50., 50., 1));
+        for (i, (x, y)) in [(90., 10.), (10., 90.), (90., 90.)].into_iter().enumerate() {
+            qt.insert(&Point::new(x, y, 2 + i as i32));
+        }
+        assert!(qt.depth() > 0);
+
+        let found = qt.query_rect(Qrect::new(50., 50., 5., 5.));
+        assert_eq!(sorted_data(&found), vec![1]);
+        assert_eq!(qt.query_rect(qt.boundary()).iter().filter(|p| p.data == 1).count(), 1);
+    }
+
+    #[test]
+    fn f64_coords_round_trip() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        let coords = [(0.1f64, 0.2f64), (33.333333333, 66.666666666), (99.99, 12.3456789)];
+        for (i, &(x, y)) in coords.iter().enumerate() {
+            assert!(qt.insert_from_f64_coords(x, y, i));
+        }
+        assert!(!qt.insert_from_f64_coords(150., 50., 9));
+
+        let mut out = qt.collect_as_f64_coords();
+        out.sort_by_key(|(_, data)| *data);
+        assert_eq!(out.len(), coords.len());
+        for (([x, y], _), &(ox, oy)) in out.iter().zip(&coords) {
+            // exactly what f32 can hold, nothing further lost on the way back out
+            assert_eq!(*x, ox as f32 as f64);
+            assert_eq!(*y, oy as f32 as f64);
+            assert!((x - ox).abs() <= ox.abs() * f32::EPSILON as f64);
+        }
+
+        let found = qt.query_circle_f64(33.3, 66.7, 1.);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].data, 1);
+    }
+
+    #[test]
+    fn query_rect_array_fills_to_capacity() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..20 {
+            qt.insert(&Point::new((i * 5) as f32, (i * 5) as f32, i));
+        }
+        let range = Qrect::screen_size(100., 100.);
+
+        let before = allocations();
+        let (slots, count) = qt.query_rect_array::<8>(&range);
+        assert_eq!(allocations(), before);
+        assert_eq!(count, 8);
+        assert!(slots.iter().all(|slot| slot.is_some()));
+
+        let (slots, count) = qt.query_rect_array::<8>(&Qrect::new(10., 10., 6., 6.));
+        assert_eq!(count, 3);
+        let found: Vec<Point<i32>> = slots.into_iter().flatten().collect();
+        assert_eq!(sorted_data(&found), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn from_items_uses_accessor() {
+        #[derive(Clone, Debug, PartialEq)]
+        struct Unit { id: u32, pos: (f32, f32) }
+
+        let units = vec![
+            Unit { id: 0, pos: (10., 10.) },
+            Unit { id: 1, pos: (20., 80.) },
+            Unit { id: 2, pos: (70., 30.) },
+            Unit { id: 3, pos: (150., 30.) },
+        ];
+        let qt = Quadtree::from_items(Qrect::screen_size(100., 100.), 1, units.clone(), |unit| unit.pos);
+        assert_eq!(qt.len(), 3);
+
+        let found = qt.query_rect(Qrect::new(70., 30., 5., 5.));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].data, units[2]);
+        assert_eq!((found[0].x, found[0].y), units[2].pos);
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn stream_round_trip() {
+        let mut seed = 5;
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        for i in 0..300u32 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let mut layered = Point::new(40., 40., 1000u32);
+        layered.layer = 3;
+        qt.insert(&layered);
+
+        let mut buffer: Vec<u8> = vec![];
+        qt.write_to_writer(&mut buffer).unwrap();
+        let loaded = Quadtree::<u32>::read_from_reader(&mut buffer.as_slice()).unwrap();
+
+        assert_eq!(loaded.structure_string(), qt.structure_string());
+        for range in [Qrect::screen_size(100., 100.), Qrect::new(30., 60., 12., 20.), Qrect::new(40., 40., 0., 0.)] {
+            assert_eq!(loaded.query_rect(&range), qt.query_rect(&range));
+        }
+        assert_eq!(loaded.query_circle(70., 20., 15.), qt.query_circle(70., 20., 15.));
+        let layers: Vec<u8> = loaded.query_rect(Qrect::new(40., 40., 0., 0.)).iter().map(|p| p.layer()).collect();
+        assert!(layers.contains(&3));
+
+        // bulk loaded cells are off center so their boundaries are written out
+        let bulk = Quadtree::bulk_load(qt.collect(), qt.boundary().clone(), 3);
+        let mut bulk_buffer: Vec<u8> = vec![];
+        bulk.write_to_writer(&mut bulk_buffer).unwrap();
+        let bulk_loaded = Quadtree::<u32>::read_from_reader(&mut bulk_buffer.as_slice()).unwrap();
+        assert_eq!(bulk_loaded.structure_string(), bulk.structure_string());
+        assert_eq!(bulk_loaded.validate(), Ok(()));
+
+        buffer[0] = b'X';
+        let err = Quadtree::<u32>::read_from_reader(&mut buffer.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("magic"));
+    }
+
+    #[test]
+    fn metadata_quadtree_tags_points() {
+        let mut qt: MetadataQuadtree<i32, &str> = MetadataQuadtree::new(Qrect::screen_size(100., 100.), 1);
+        let a = qt.insert_with_metadata(&Point::new(10., 10., 0), "player").unwrap();
+        let b = qt.insert(&Point::new(12., 12., 1)).unwrap();
+        let c = qt.insert_with_metadata(&Point::new(80., 80., 2), "npc").unwrap();
+        assert_eq!(qt.insert_with_metadata(&Point::new(200., 10., 3), "lost"), None);
+        assert_eq!(qt.len(), 3);
+
+        assert_eq!(qt.get_metadata(a), Some(&"player"));
+        assert_eq!(qt.get_metadata(b), None);
+        assert_eq!(qt.get(c).map(|p| p.data), Some(2));
+
+        let mut found = qt.query_rect_with_metadata(&Qrect::new(10., 10., 5., 5.));
+        found.sort_by_key(|(point, _)| point.data);
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].0.data, found[0].1), (0, Some(&"player")));
+        assert_eq!((found[1].0.data, found[1].1), (1, None));
+
+        assert_eq!(qt.set_metadata(b, "pet"), None);
+        assert_eq!(qt.set_metadata(a, "ghost"), Some("player"));
+        *qt.get_metadata_mut(c).unwrap() = "boss";
+        let found = qt.query_circle_with_metadata(80., 80., 1.);
+        assert_eq!((found[0].0.data, found[0].1), (2, Some(&"boss")));
+
+        assert!(qt.move_point(c, 30., 30.));
+        assert!(!qt.move_point(c, 300., 30.));
+        assert!(qt.query_circle_with_metadata(80., 80., 1.).is_empty());
+        assert_eq!(qt.query_circle_with_metadata(30., 30., 1.)[0].1, Some(&"boss"));
+
+        assert_eq!(qt.remove(a).map(|(point, metadata)| (point.data, metadata)), Some((0, Some("ghost"))));
+        assert_eq!(qt.remove(a), None);
+        assert_eq!(qt.remove_metadata(b), Some("pet"));
+        assert_eq!(qt.set_metadata(a, "back"), None);
+        assert_eq!(qt.get_metadata(a), None);
+        assert_eq!(qt.len(), 2);
+        assert_eq!(qt.query_rect_with_metadata(&Qrect::screen_size(100., 100.)).len(), 2);
+    }
+
+    #[test]
+    fn snapshot_diff_apply_then_revert() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..12 {
+            qt.insert(&Point::new((i * 8) as f32, (i * 5) as f32, i));
+        }
+        let original = qt.clone();
+
+        let mut edited = qt.clone();
+        edited.remove(&Point::new(16., 10., 2));
+        edited.remove(&Point::new(64., 40., 8));
+        edited.insert(&Point::new(33., 77., 12));
+        edited.insert(&Point::new(16., 10., 2));
+        edited.insert(&Point::new(16., 10., 2));
+        let diff = QuadtreeSnapshot::diff(&original.snapshot(), &edited.snapshot());
+        assert_eq!(diff.added.len(), 2);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.inverse().added, diff.removed);
+        assert_eq!(diff.inverse().removed, diff.added);
+
+        qt.apply_diff(&diff);
+        assert_eq!(sorted_data(&qt.collect()), sorted_data(&edited.collect()));
+        diff.revert(&mut qt);
+        assert_eq!(sorted_data(&qt.collect()), sorted_data(&original.collect()));
+        assert!(qt.structural_check());
+    }
+
+    #[test]
+    fn local_density_tracks_crowding() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        assert_eq!(qt.local_density(50., 50., 5.), 0.);
+        assert_eq!(qt.uniform_density(), 0.);
+
+        qt.insert(&Point::new(90., 90., 0));
+        let far_only = qt.local_density(50., 50., 5.);
+        assert_eq!(far_only, 0.);
+
+        let mut last = far_only;
+        let mut near = vec![];
+        for i in 0..5 {
+            let point = Point::new(50. + i as f32, 50., i + 1);
+            qt.insert(&point);
+            near.push(point);
+            let density = qt.local_density(50., 50., 5.);
+            assert!(density > last);
+            last = density;
+        }
+        for point in &near {
+            qt.remove(point);
+            let density = qt.local_density(50., 50., 5.);
+            assert!(density < last);
+            last = density;
+        }
+
+        qt.insert(&Point::new(10., 10., 6));
+        // two points spanning an 80 by 80 box
+        assert_eq!(qt.uniform_density(), 2. / 6400.);
+    }
+
+    #[test]
+    fn query_mask_checkerboard() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        let mut id = 0;
+        for y in 0..10 {
+            for x in 0..10 {
+                qt.insert(&Point::new(x as f32 * 10. + 5., y as f32 * 10. + 5., id));
+                id += 1;
+            }
+        }
+        qt.insert(&Point::new(100., 100., id));
+
+        // 4 by 4 board of 25 unit cells, on where col + row is even
+        let mask: Vec<bool> = (0..16).map(|i| (i % 4 + i / 4) % 2 == 0).collect();
+        let on = |p: &Point<i32>| {
+            let col = ((p.x / 25.) as usize).min(3);
+            let row = ((p.y / 25.) as usize).min(3);
+            (col + row).is_multiple_of(2)
+        };
+        let found = qt.query_mask(&mask, 4, 4);
+        assert!(found.iter().all(on));
+        let expected = qt.collect().iter().filter(|p| on(p)).count();
+        assert_eq!(found.len(), expected);
+        assert!(found.iter().any(|p| p.data == id));
+        assert!(qt.query_mask(&[false; 16], 4, 4).is_empty());
+    }
+
+    #[test]
+    fn insert_batch_from_csv_collects_errors() {
+        let csv = "10,20,1\n\n30.5, 40 ,2\nabc,1,3\n5,5,four\n500,5,5\n7\n60,70,6\n";
+        let mut qt: Quadtree<i32> = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        let err = qt.insert_batch_from_csv(csv.as_bytes(), &CsvConfig::default()).unwrap_err();
+
+        assert_eq!(err.inserted, 3);
+        assert_eq!(err.errors.iter().map(|e| e.line).collect::<Vec<_>>(), vec![4, 5, 6, 7]);
+        assert!(err.errors[0].message.starts_with("bad x"));
+        assert!(err.errors[1].message.starts_with("bad data"));
+        assert!(err.errors[2].message.contains("outside"));
+        assert!(err.errors[3].message.starts_with("missing y"));
+
+        let mut found = qt.collect();
+        found.sort_by_key(|p| p.data);
+        assert_eq!(found.iter().map(|p| (p.x, p.y, p.data)).collect::<Vec<_>>(), vec![(10., 20., 1), (30.5, 40., 2), (60., 70., 6)]);
+
+        // data first, then y, then x
+        let config = CsvConfig { x_col: 2, y_col: 1, data_col: 0 };
+        let mut qt: Quadtree<String> = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        assert_eq!(qt.insert_batch_from_csv("tree,15,25\nrock,35,45\n".as_bytes(), &config), Ok(2));
+        assert_eq!(qt.query_rect(Qrect::range(25., 15., 1.))[0].data, "tree");
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn rebuild_parallel_matches_sequential() {
+        let mut seed = 17;
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        for i in 0..3000 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let sequential = Quadtree::bulk_load(qt.collect(), qt.boundary.clone(), 4);
+
+        qt.rebuild_parallel();
+        assert_eq!(sorted_data(&qt.collect()), sorted_data(&sequential.collect()));
+        assert_eq!(qt.structure_string(), sequential.structure_string());
+        assert!(qt.structural_check());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_iter_rect_matches_sequential_sum() {
+        use rayon::prelude::*;
+
+        let mut seed = 41;
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        for i in 0..3000 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i as i64));
+        }
+
+        for range in [Qrect::new(40., 60., 25., 15.), qt.boundary.clone(), Qrect::new(500., 500., 1., 1.)] {
+            let sequential: i64 = qt.iter_rect(&range).map(|point| point.data).sum();
+            let parallel: i64 = qt.par_iter_rect(&range).map(|point| point.data).sum();
+            assert_eq!(parallel, sequential);
+            assert_eq!(qt.par_iter_rect(&range).count(), qt.count_in_rect(&range));
+        }
+    }
+
+    #[test]
+    fn leaf_point_distribution_counts_leaves() {
+        let capacity = 4;
+        let mut points = vec![];
+        for (cx, cy) in [(25., 25.), (75., 25.), (25., 75.), (75., 75.)] {
+            for i in 0..capacity {
+                points.push(Point::new(cx + i as f32, cy, points.len()));
+            }
+        }
+        let qt = Quadtree::bulk_load(points, Qrect::screen_size(100., 100.), capacity);
+        assert_eq!(qt.leaf_point_distribution(), BTreeMap::from([(capacity, 4)]));
+        assert_eq!(qt.leaf_utilization_cdf(), vec![(capacity, 1.)]);
+
+        let points = vec![Point::new(10., 10., 0), Point::new(90., 10., 1), Point::new(91., 10., 2)];
+        let qt = Quadtree::bulk_load(points, Qrect::screen_size(100., 100.), 2);
+        assert_eq!(qt.leaf_point_distribution(), BTreeMap::from([(0, 2), (1, 1), (2, 1)]));
+        assert_eq!(qt.leaf_utilization_cdf(), vec![(0, 0.5), (1, 0.75), (2, 1.)]);
+    }
+
+    #[test]
+    fn approx_nearest_is_close() {
+        let mut seed = 23;
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        assert_eq!(qt.approx_nearest(50., 50.), None);
+        for i in 0..500 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+
+        for _ in 0..50 {
+            let (x, y) = (lcg(&mut seed) * 100., lcg(&mut seed) * 100.);
+            let exact = qt.nearest_where(x, y, |_| true).unwrap();
+            let approx = qt.approx_nearest(x, y).unwrap();
+            let exact_dist = ((exact.x - x).powi(2) + (exact.y - y).powi(2)).sqrt();
+            let approx_dist = ((approx.x - x).powi(2) + (approx.y - y).powi(2)).sqrt();
+            assert!(approx_dist >= exact_dist);
+            // never further than a leaf's diagonal or so from the true answer
+            assert!(approx_dist <= exact_dist + 15., "{} vs {}", approx_dist, exact_dist);
+        }
+
+        // a lone point far from the query is still found
+        let mut sparse = Quadtree::new(Qrect::screen_size(100., 100.), 1);
+        sparse.insert(&Point::new(5., 5., 0));
+        sparse.insert(&Point::new(6., 6., 1));
+        assert!(sparse.approx_nearest(95., 95.).is_some());
+    }
+
+    #[test]
+    fn find_isolated_points_skips_clusters() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for (i, (x, y)) in [(20., 20.), (21., 20.), (20., 22.), (23., 21.), (22., 23.)].into_iter().enumerate() {
+            qt.insert(&Point::new(x, y, i as i32));
+        }
+        qt.insert(&Point::new(80., 20., 5));
+        qt.insert(&Point::new(50., 90., 6));
+
+        let isolated = qt.find_isolated_points(10.);
+        let mut ids: Vec<i32> = isolated.iter().map(|p| p.data).collect();
+        ids.sort();
+        assert_eq!(ids, vec![5, 6]);
+        assert!(qt.find_isolated_points(100.).is_empty());
+        assert_eq!(qt.find_isolated_points(0.5).len(), 7);
+    }
+
+    #[test]
+    fn query_rect_accepts_tuples() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..10 {
+            qt.insert(&Point::new((i * 10) as f32, (i * 10) as f32, i));
+        }
+
+        let by_corners = qt.query_rect(((0., 0.), (25., 25.)));
+        assert_eq!(sorted_data(&by_corners), vec![0, 1, 2]);
+        let by_center = qt.query_rect((50., 50., 5., 5.));
+        assert_eq!(sorted_data(&by_center), vec![5]);
+
+        let rect = Qrect::new(50., 50., 5., 5.);
+        assert_eq!(qt.query_rect(&rect), qt.query_rect(rect.clone()));
+        assert_eq!(qt.iter_rect(((0., 0.), (25., 25.))).count(), 3);
+    }
+
+    #[test]
+    fn iter_rev_reverses_iter() {
+        let mut seed = 29;
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        for i in 0..200 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+
+        let forward: Vec<i32> = qt.iter().map(|p| p.data).collect();
+        let mut backward: Vec<i32> = qt.iter().rev().map(|p| p.data).collect();
+        assert_eq!(forward.len(), 200);
+        backward.reverse();
+        assert_eq!(backward, forward);
+
+        // taking from both ends meets in the middle without repeats
+        let range = Qrect::new(40., 60., 30., 25.);
+        let expected: Vec<i32> = qt.iter_rect(&range).map(|p| p.data).collect();
+        let mut iter = qt.iter_rect(&range);
+        let mut front = vec![];
+        let mut back = vec![];
+        while let Some(p) = iter.next() {
+            front.push(p.data);
+            match iter.next_back() {
+                Some(p) => back.push(p.data),
+                None => break,
+            }
+        }
+        back.reverse();
+        front.extend(back);
+        assert_eq!(front, expected);
+    }
+
+    #[test]
+    fn tighten_boundary_fits_points() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        assert!(!qt.is_boundary_tight());
+        qt.tighten_boundary();
+        assert_eq!(qt.boundary(), &Qrect::screen_size(100., 100.));
+
+        for (i, (x, y)) in [(20., 30.), (60., 35.), (40., 70.), (25., 50.), (55., 65.)].into_iter().enumerate() {
+            qt.insert(&Point::new(x, y, i as i32));
+        }
+        assert!(!qt.is_boundary_tight());
+        assert_eq!(qt.min_enclosing_rect(), qt.bounding_box());
+
+        qt.tighten_boundary();
+        assert!(qt.is_boundary_tight());
+        assert_eq!(qt.boundary(), &Qrect::corners((20., 30.), (60., 70.)));
+        assert_eq!(sorted_data(&qt.collect()), vec![0, 1, 2, 3, 4]);
+        assert!(qt.structural_check());
+    }
+
+    #[test]
+    fn memory_bytes_split_into_structure_and_data() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        let node = std::mem::size_of::<Quadtree<i32>>();
+        assert_eq!(qt.structural_memory_bytes(), node);
+        assert_eq!(qt.data_memory_bytes(), 0);
+        assert!(qt.memory_overhead_ratio().is_infinite());
+
+        let mut seed = 31;
+        for i in 0..300 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        assert_eq!(qt.data_memory_bytes(), 300 * std::mem::size_of::<Point<i32>>());
+        assert_eq!(qt.structural_memory_bytes() + qt.data_memory_bytes(), qt.approximate_memory_bytes());
+        assert!(qt.structural_memory_bytes() >= qt.node_count() * node);
+
+        // a flat list of the same points costs less per point than the tree
+        let mut flat = Quadtree::new(Qrect::screen_size(100., 100.), 1000);
+        for point in qt.collect() {
+            flat.insert(&point);
+        }
+        assert!(flat.memory_overhead_ratio() < qt.memory_overhead_ratio());
+    }
+
+    #[test]
+    fn query_rect_node_budget_stops_early() {
+        let mut seed = 37;
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..400 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let range = Qrect::new(50., 50., 30., 30.);
+        let all = qt.query_rect(&range);
+
+        let (partial, complete) = qt.query_rect_node_budget(&range, 5);
+        assert!(!complete);
+        assert!(partial.len() < all.len());
+        assert!(partial.iter().all(|p| all.contains(p)));
+
+        let (everything, complete) = qt.query_rect_node_budget(&range, qt.node_count());
+        assert!(complete);
+        assert_eq!(sorted_data(&everything), sorted_data(&all));
+        assert_eq!(qt.query_rect_node_budget(&Qrect::new(500., 500., 1., 1.), 0), (vec![], true));
+    }
+
+    #[test]
+    fn pairs_within_finds_each_pair_once() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 1);
+        for (i, (x, y)) in [(10., 10.), (12., 10.), (50., 50.), (50., 53.), (51., 51.), (90., 10.), (30., 80.), (30., 80.)].into_iter().enumerate() {
+            qt.insert(&Point::new(x, y, i as i32));
+        }
+
+        let mut pairs: Vec<(i32, i32)> = qt.pairs_within(5.).into_iter()
+            .map(|(a, b)| (a.data.min(b.data), a.data.max(b.data)))
+            .collect();
+        pairs.sort();
+        assert_eq!(pairs, vec![(0, 1), (2, 3), (2, 4), (3, 4), (6, 7)]);
+        assert!(qt.pairs_within(1.).iter().all(|(a, b)| a.data + b.data == 13));
+
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        let mut seed = 71;
+        for i in 0..600 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let all = qt.collect();
+        let mut brute = vec![];
+        for (i, a) in all.iter().enumerate() {
+            for b in &all[i + 1..] {
+                if (a.x - b.x).powi(2) + (a.y - b.y).powi(2) < 16. {
+                    brute.push((a.data.min(b.data), a.data.max(b.data)));
+                }
+            }
+        }
+        brute.sort();
+        let mut pairs: Vec<(i32, i32)> = qt.pairs_within(4.).into_iter()
+            .map(|(a, b)| (a.data.min(b.data), a.data.max(b.data)))
+            .collect();
+        pairs.sort();
+        assert_eq!(pairs, brute);
+    }
+
+    #[test]
+    fn query_viewport_maps_to_screen() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        qt.insert(&Point::new(25., 25., 0));
+        qt.insert(&Point::new(40., 10., 1));
+        qt.insert(&Point::new(75., 75., 2));
+
+        let world = Qrect::screen_size(100., 100.);
+        let screen = Qrect::screen_size(200., 200.);
+        let viewport = Qrect::corners((0., 0.), (100., 100.));
+        let visible = viewport.viewport_to_world(&world, &screen);
+        assert_eq!(visible, Qrect::corners((0., 0.), (50., 50.)));
+
+        let mut found = qt.query_viewport(&visible, |x, y| (x * 2., y * 2.));
+        found.sort_by_key(|(point, _)| point.data);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].1, (50., 50.));
+        assert_eq!(found[1].1, (80., 20.));
+    }
+
+    #[cfg(feature = "geojson")]
+    #[test]
+    fn to_geojson_feature_collection() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        let points = [Point::new(10.5, 20., "a"), Point::new(30., 40.25, "b"), Point::new(80., 80., "c")];
+        for point in &points {
+            qt.insert(point);
+        }
+
+        let json: serde_json::Value = serde_json::from_str(&qt.to_geojson()).unwrap();
+        assert_eq!(json["type"], "FeatureCollection");
+        let features = json["features"].as_array().unwrap();
+        assert_eq!(features.len(), 3);
+        for point in &points {
+            let feature = features.iter().find(|f| f["properties"]["data"] == point.data).unwrap();
+            assert_eq!(feature["geometry"]["type"], "Point");
+            assert_eq!(feature["geometry"]["coordinates"][0].as_f64().unwrap(), point.x as f64);
+            assert_eq!(feature["geometry"]["coordinates"][1].as_f64().unwrap(), point.y as f64);
+        }
+
+        let json: serde_json::Value = serde_json::from_str(&qt.to_geojson_rect(&Qrect::new(20., 30., 15., 15.))).unwrap();
+        assert_eq!(json["features"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn region_data_mut_updates_zone() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..30 {
+            qt.insert(&Point::new((i * 37 % 100) as f32, (i * 13 % 100) as f32, 0));
+        }
+        let zone = Qrect::new(50., 50., 25., 25.);
+        let inside = qt.query_rect(&zone).len();
+        assert!(inside > 0);
+
+        let mut cache = CachedQuery::new();
+        cache.get(&qt, &zone);
+        for data in qt.region_data_mut(&zone) {
+            *data += 10;
+        }
+
+        assert!(cache.get(&qt, &zone).iter().all(|p| p.data == 10));
+        assert_eq!(cache.hits, 0);
+        assert_eq!(qt.iter().filter(|p| p.data == 10).count(), inside);
+        assert_eq!(qt.iter().filter(|p| p.data == 0).count(), 30 - inside);
+        assert!(qt.structural_check());
+    }
+
+    #[test]
+    fn cached_counts_follow_edits() {
+        let mut seed = 41;
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        let mut points = vec![];
+        for i in 0..300 {
+            let point = Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i);
+            assert!(qt.insert(&point));
+            points.push(point);
+        }
+        assert!(!qt.insert(&Point::new(-5., 50., 300)));
+        assert_eq!(qt.len(), 300);
+
+        let ranges = [Qrect::new(50., 50., 25., 25.), Qrect::new(20., 70., 30., 10.), Qrect::screen_size(100., 100.)];
+        let check = |qt: &Quadtree<i32>| {
+            assert!(qt.structural_check());
+            assert_eq!(qt.len(), qt.iter().count());
+            for range in &ranges {
+                assert_eq!(qt.count_in_rect(range), qt.query_rect(range).len());
+            }
+        };
+        check(&qt);
+
+        for point in points.iter().step_by(3) {
+            assert!(qt.remove(point));
+        }
+        assert!(!qt.remove(&points[0]));
+        assert_eq!(qt.len(), 200);
+        check(&qt);
+
+        assert_eq!(qt.remove_all(&points[1..60]), 40);
+        check(&qt);
+        qt.adaptive_insert(&Point::new(10., 10., 400));
+        qt.merge_aligned(&qt.clone());
+        check(&qt);
+        qt.expand_boundary(Qrect::screen_size(200., 200.));
+        check(&qt);
+
+        qt.empty();
+        assert_eq!(qt.len(), 0);
+        check(&qt);
+    }
+
+    #[test]
+    fn insert_with_path_reports_node() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 1);
+        assert_eq!(qt.insert_with_path(&Point::new(10., 10., 0)), Some(vec![]));
+        assert_eq!(qt.insert_with_path(&Point::new(20., 20., 1)), Some(vec![Quadrant::TopLeft]));
+        assert_eq!(qt.insert_with_path(&Point::new(80., 80., 2)), Some(vec![Quadrant::BottomRight]));
+        assert_eq!(qt.insert_with_path(&Point::new(30., 40., 3)), Some(vec![Quadrant::TopLeft, Quadrant::BottomRight]));
+        assert_eq!(qt.insert_with_path(&Point::new(150., 40., 4)), None);
+
+        let path = qt.insert_with_path(&Point::new(90., 10., 5)).unwrap();
+        assert_eq!(path, vec![Quadrant::TopRight]);
+        let node = qt.node_at_path(&path).unwrap();
+        assert!(node.points().iter().any(|p| p.data == 5));
+        assert!(qt.node_at_path(&[Quadrant::BottomLeft, Quadrant::TopLeft]).is_none());
+        assert_eq!(qt.len(), 5);
+        assert!(qt.structural_check());
+    }
+
+    #[test]
+    fn detach_and_reattach_subtree() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 1);
+        for (i, (x, y)) in [(50., 50.), (10., 10.), (20., 30.), (40., 5.), (80., 80.), (70., 20.)].into_iter().enumerate() {
+            qt.insert(&Point::new(x, y, i as i32));
+        }
+        let all = sorted_data(&qt.collect());
+        let top_left = sorted_data(&qt.query_rect(Qrect::corners((0., 0.), (49.9, 49.9))));
+        assert_eq!(top_left, vec![1, 2, 3]);
+
+        let subtree = qt.delete_subtree_at(&[Quadrant::TopLeft]).unwrap();
+        assert_eq!(sorted_data(&subtree.collect()), top_left);
+        assert!(qt.query_rect(Qrect::corners((0., 0.), (49.9, 49.9))).is_empty());
+        assert_eq!(qt.len(), all.len() - top_left.len());
+        assert!(qt.structural_check());
+        assert!(qt.delete_subtree_at(&[]).is_none());
+
+        // only an empty node with the same boundary can take it
+        assert!(!qt.attach_subtree_at(&[Quadrant::BottomRight], subtree.clone()));
+        assert!(!qt.attach_subtree_at(&[Quadrant::TopLeft, Quadrant::TopLeft], subtree.clone()));
+        assert!(qt.attach_subtree_at(&[Quadrant::TopLeft], subtree));
+        assert_eq!(sorted_data(&qt.collect()), all);
+        assert_eq!(qt.len(), all.len());
+        assert!(qt.structural_check());
+    }
+
+    #[test]
+    fn try_for_each_in_rect_breaks_early() {
+        use std::ops::ControlFlow;
+
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..40 {
+            qt.insert(&Point::new((i * 7 % 100) as f32, (i * 11 % 100) as f32, i));
+        }
+        let range = Qrect::new(50., 50., 40., 40.);
+
+        let mut seen = 0;
+        let found = qt.try_for_each_in_rect(&range, |point| {
+            seen += 1;
+            if point.data % 5 == 3 { ControlFlow::Break(point.data) } else { ControlFlow::Continue(()) }
+        });
+        let expected = qt.iter_rect(&range).find(|p| p.data % 5 == 3).unwrap();
+        assert_eq!(found, ControlFlow::Break(expected.data));
+        assert!(seen < qt.query_rect(&range).len());
+
+        let before = allocations();
+        let none: ControlFlow<i32> = qt.try_for_each_in_rect(&range, |_| ControlFlow::Continue(()));
+        assert_eq!(allocations(), before);
+        assert_eq!(none, ControlFlow::Continue(()));
+
+        let mut total = 0;
+        qt.for_each_in_rect(&range, |point| total += point.data);
+        assert_eq!(total, qt.query_rect(&range).iter().map(|p| p.data).sum::<i32>());
+    }
+
+    #[test]
+    fn centroids_of_symmetric_points() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        // a ring of points around (40, 60) with heavier points on one side
+        for (dx, dy) in [(10., 0.), (-10., 0.), (0., 10.), (0., -10.), (7., 7.), (-7., -7.), (7., -7.), (-7., 7.)] {
+            qt.insert(&Point::new(40. + dx, 60. + dy, if dx > 0. { 3. } else { 1. }));
+        }
+        qt.insert(&Point::new(95., 5., 1.));
+
+        let range = Qrect::new(40., 60., 15., 15.);
+        assert_eq!(qt.centroid_of_rect(&range), Some((40., 60.)));
+        assert_eq!(qt.centroid_of_circle(40., 60., 11.), Some((40., 60.)));
+        assert_eq!(qt.centroid_of_rect(&Qrect::new(10., 10., 5., 5.)), None);
+        assert_eq!(qt.centroid_of_circle(10., 10., 5.), None);
+
+        assert_eq!(qt.centroid_weighted_in_rect(&range, |_| 1.), Some((40., 60.)));
+        let (wx, wy) = qt.centroid_weighted_in_rect(&range, |&w| w).unwrap();
+        assert!(wx > 40.);
+        assert_eq!(wy, 60.);
+        assert_eq!(qt.centroid_weighted_in_rect(&range, |_| 0.), None);
+    }
+
+    #[test]
+    fn merged_builds_fresh_tree() {
+        let mut left = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        let mut right = Quadtree::new(Qrect::corners((200., 0.), (300., 100.)), 2);
+        for i in 0..10 {
+            left.insert(&Point::new((i * 9) as f32, (i * 7) as f32, i));
+            right.insert(&Point::new(200. + (i * 9) as f32, (i * 7) as f32, 10 + i));
+        }
+
+        let both = left.merged(&right, Qrect::corners((0., 0.), (300., 100.)), 4);
+        assert_eq!(sorted_data(&both.collect()), (0..20).collect::<Vec<_>>());
+        assert_eq!(both.capacity(), 4);
+        assert_eq!(left.len(), 10);
+        assert_eq!(right.len(), 10);
+        assert!(both.structural_check());
+
+        let clipped = left.merged(&right, Qrect::screen_size(100., 100.), 4);
+        assert_eq!(sorted_data(&clipped.collect()), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn insert_at_centroid_anchors_cluster() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 3);
+        assert!(!qt.insert_at_centroid(-1));
+        for i in 0..10 {
+            qt.insert(&Point::new(30. + (i % 4) as f32 * 2., 40. + (i / 4) as f32 * 3., i));
+        }
+        let (cx, cy) = qt.centroid().unwrap();
+        assert!(qt.insert_at_centroid(100));
+        let anchor = qt.iter().find(|p| p.data == 100).unwrap();
+        assert_eq!((anchor.x, anchor.y), (cx, cy));
+
+        qt.insert(&Point::new(80., 80., 11));
+        qt.insert(&Point::new(90., 70., 12));
+        let zone = Qrect::new(85., 75., 10., 10.);
+        assert!(qt.insert_at_centroid_of_rect(&zone, 200));
+        let anchor = qt.iter().find(|p| p.data == 200).unwrap();
+        assert_eq!((anchor.x, anchor.y), (85., 75.));
+        assert!(!qt.insert_at_centroid_of_rect(&Qrect::new(10., 90., 5., 5.), 300));
+    }
+
+    #[test]
+    fn to_dot_has_a_node_per_tree_node() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..30 {
+            qt.insert(&Point::new((i * 37 % 100) as f32, (i * 13 % 100) as f32, i));
+        }
+
+        let dot = qt.to_dot();
+        assert!(dot.starts_with("digraph quadtree {"));
+        assert!(dot.trim_end().ends_with('}'));
+        let nodes = dot.lines().filter(|line| line.contains("[label=\"(")).count();
+        let edges = dot.lines().filter(|line| line.contains("->")).count();
+        assert_eq!(nodes, qt.node_count());
+        assert_eq!(edges, qt.node_count() - 1);
+        assert!(dot.contains("n0 [label=\"(50, 50) ±50,50\\npoints=2\"];"));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn estimate_count_sampled_is_close() {
+        use rand::SeedableRng;
+
+        let mut seed = 43;
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        for i in 0..5000 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+
+        // every single estimate lands close, not just their mean
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for fraction in [0.1, 0.5] {
+            for _ in 0..100 {
+                let estimate = qt.estimate_count_sampled(fraction, &mut rng);
+                let error = (estimate as f32 - qt.len() as f32).abs() / qt.len() as f32;
+                assert!(error < 0.2, "estimate {} off by {} at {}", estimate, error, fraction);
+            }
+        }
+        assert_eq!(qt.estimate_count_sampled(1., &mut rng), qt.len());
+        assert_eq!(qt.estimate_count_sampled(0., &mut rng), 0);
+        assert_eq!(qt.estimate_count_sampled(f32::NAN, &mut rng), 0);
+    }
+
+    #[test]
+    fn query_rect_map_matches_query_rect() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        for i in 0..40 {
+            qt.insert(&Point::new((i * 37 % 100) as f32, (i * 13 % 100) as f32, format!("unit {}", i)));
+        }
+        let range = Qrect::new(40., 60., 30., 25.);
+
+        let lengths = qt.query_rect_map(&range, |point| point.data.len());
+        let expected: Vec<usize> = qt.query_rect(&range).iter().map(|point| point.data.len()).collect();
+        assert_eq!(lengths, expected);
+        assert!(!lengths.is_empty());
+
+        let names = qt.query_rect_map(&range, |point| point.data.clone());
+        assert_eq!(names, qt.query_rect(&range).into_iter().map(|point| point.data).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn auto_rebalance_caps_balance_factor() {
+        let threshold = 3.;
+        let mut seed = 47;
+        let mut plain = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        let mut balanced = Quadtree::new(Qrect::screen_size(100., 100.), 2).with_auto_rebalance(threshold);
+        for i in 0..200 {
+            // most points pile onto one spot
+            let point = if i % 4 == 0 {
+                Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i)
+            } else {
+                Point::new(30., 30., i)
+            };
+            plain.insert(&point);
+            assert!(balanced.insert(&point));
+            assert!(balanced.balance_factor() <= threshold, "{} after {}", balanced.balance_factor(), i);
+        }
+        assert_eq!(balanced.capacity(), 2);
+
+        assert!(plain.balance_factor() > threshold);
+        assert_eq!(sorted_data(&balanced.collect()), sorted_data(&plain.collect()));
+        assert!(balanced.structural_check());
+        assert!(!balanced.insert(&Point::new(500., 500., -1)));
+
+        // distinct points bunched in a corner are deep by nature, rebuilds wait for len to double
+        // so the tree may sit above threshold in between but keeps its capacity
+        let mut deep = Quadtree::new(Qrect::screen_size(100., 100.), 2).with_auto_rebalance(1.);
+        for i in 0..500 {
+            assert!(deep.insert(&Point::new(i as f32 * 1e-3, i as f32 * 1e-3, i)));
+        }
+        assert_eq!(deep.len(), 500);
+        assert_eq!(deep.capacity(), 2);
+        assert_eq!(deep.validate(), Ok(()));
+    }
+
+    #[test]
+    fn query_n_nearest_in_circle_matches_naive() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        let mut seed = 23;
+        for i in 0..2000 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+
+        for (cx, cy, r, n) in [(50., 50., 10., 5), (5., 95., 30., 20), (70., 20., 2., 50), (30., 30., 200., 1), (30., 30., 20., 0)] {
+            let dist = |p: &Point<i32>| (p.x - cx).powi(2) + (p.y - cy).powi(2);
+            let mut naive = qt.query_circle(cx, cy, r);
+            naive.sort_by(|a, b| dist(a).total_cmp(&dist(b)));
+            naive.truncate(n);
+
+            let found = qt.query_n_nearest_in_circle(cx, cy, r, n);
+            assert_eq!(found.len(), naive.len());
+            let found_dists: Vec<f32> = found.iter().map(dist).collect();
+            let naive_dists: Vec<f32> = naive.iter().map(dist).collect();
+            assert_eq!(found_dists, naive_dists);
+        }
+    }
+
+    #[test]
+    fn clear_rect_and_circle_remove_only_inside() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        let mut seed = 31;
+        for i in 0..1000 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let before = qt.collect();
+
+        let range = Qrect::new(30., 40., 15., 10.);
+        let expected = qt.count_in_rect(&range);
+        assert!(expected > 0);
+        assert_eq!(qt.clear_rect(&range), expected);
+        assert_eq!(qt.count_in_rect(&range), 0);
+        assert_eq!(qt.len(), 1000 - expected);
+        assert!(qt.structural_check());
+
+        let mut outside: Vec<i32> = before.iter().filter(|p| !range.contains_point(*p)).map(|p| p.data).collect();
+        outside.sort();
+        let mut left: Vec<i32> = qt.collect().iter().map(|p| p.data).collect();
+        left.sort();
+        assert_eq!(left, outside);
+
+        let in_circle = qt.query_circle(70., 70., 12.).len();
+        assert!(in_circle > 0);
+        assert_eq!(qt.clear_circle(70., 70., 12.), in_circle);
+        assert!(qt.query_circle(70., 70., 12.).is_empty());
+        assert_eq!(qt.len(), 1000 - expected - in_circle);
+
+        // drain hands back the same points clear would have dropped
+        let drained = qt.drain_rect(&Qrect::new(50., 50., 50., 50.));
+        assert_eq!(drained.len(), 1000 - expected - in_circle);
+        assert_eq!(qt.len(), 0);
+        assert!(!qt.divided);
+    }
+
+    #[test]
+    fn point_exists_near_matches_query_circle() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        let mut seed = 53;
+        for i in 0..2000 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+
+        for i in 0..1000 {
+            let (x, y) = (lcg(&mut seed) * 110. - 5., lcg(&mut seed) * 110. - 5.);
+            let epsilon = [0.01, 0.5, 1., 3.][i % 4];
+            assert_eq!(qt.point_exists_near(x, y, epsilon), !qt.query_circle(x, y, epsilon).is_empty());
+        }
+
+        // exact lookups of stored positions
+        for point in qt.collect().iter().take(100) {
+            assert!(qt.point_exists_near(point.x, point.y, 1e-4));
+        }
+        assert!(!qt.point_exists_near(50., 50., 0.));
+    }
+
+    #[test]
+    fn take_matching_removes_negative_data() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        let mut seed = 59;
+        for i in 0..1000 {
+            let data = if i % 3 == 0 { -i } else { i };
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., data));
+        }
+        let negatives = qt.collect().iter().filter(|p| p.data < 0).count();
+
+        let taken = qt.take_matching(|p| p.data < 0);
+        assert_eq!(taken.len(), negatives);
+        assert!(taken.iter().all(|p| p.data < 0));
+        assert_eq!(qt.len(), 1000 - negatives);
+        assert!(qt.collect().iter().all(|p| p.data >= 0));
+        assert!(qt.structural_check());
+
+        assert_eq!(qt.take_matching(|_| true).len(), 1000 - negatives);
+        assert!(!qt.divided);
+    }
+
+    #[test]
+    fn merge_with_sums_colliding_points() {
+        let mut a = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        a.insert(&Point::new(10., 10., 1));
+        a.insert(&Point::new(20., 20., 2));
+        a.insert(&Point::new(30., 30., 3));
+
+        let mut b = Quadtree::new(Qrect::screen_size(100., 100.), 2);
+        b.insert(&Point::new(20., 20., 10));
+        b.insert(&Point::new(30., 30., 20));
+        b.insert(&Point::new(40., 40., 30));
+
+        assert_eq!(a.merge_with(&b, |x, y| x + y), 0);
+        assert_eq!(a.len(), 4);
+        assert_eq!(sorted_data(&a.collect()), vec![1, 12, 23, 30]);
+        assert_eq!(a.query_rect(Qrect::new(20., 20., 0., 0.))[0].data, 12);
+        assert!(a.structural_check());
+
+        // points past a's boundary are counted and left out
+        let mut c = Quadtree::new(Qrect::screen_size(200., 200.), 2);
+        c.insert(&Point::new(10., 10., 100));
+        c.insert(&Point::new(150., 20., 5));
+        c.insert(&Point::new(20., 180., 6));
+        assert_eq!(a.merge_with(&c, |x, y| x + y), 2);
+        assert_eq!(a.len(), 4);
+        assert_eq!(sorted_data(&a.collect()), vec![12, 23, 30, 101]);
+    }
+
+    #[test]
+    fn voronoi_owner_of_cardinal_points() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 1);
+        assert!(qt.voronoi_cell_owner(50., 50.).is_none());
+
+        qt.insert(&Point::new(50., 10., 0)); // north
+        qt.insert(&Point::new(90., 50., 1)); // east
+        qt.insert(&Point::new(50., 90., 2)); // south
+        qt.insert(&Point::new(10., 50., 3)); // west
+
+        for (x, y, owner) in [(50., 0., 0), (55., 30., 0), (99., 45., 1), (70., 55., 1), (40., 99., 2), (52., 70., 2), (0., 40., 3), (30., 48., 3)] {
+            assert_eq!(qt.voronoi_cell_owner(x, y).unwrap().data, owner);
+        }
+
+        let candidates: Vec<i32> = qt.voronoi_n_candidates(60., 20., 2).iter().map(|p| p.data).collect();
+        assert_eq!(candidates, vec![0, 1]);
+        assert_eq!(qt.voronoi_n_candidates(60., 20., 10).len(), 4);
+        assert!(qt.voronoi_n_candidates(60., 20., 0).is_empty());
+    }
+
+    #[test]
+    fn adjacency_list_matches_brute_force() {
+        let mut qt = Quadtree::new(Qrect::screen_size(100., 100.), 4);
+        let mut seed = 61;
+        for i in 0..1000 {
+            qt.insert(&Point::new(lcg(&mut seed) * 100., lcg(&mut seed) * 100., i));
+        }
+        let radius = 4.;
+
+        let adjacency = qt.to_adjacency_list(radius);
+        assert_eq!(adjacency.len(), 1000);
+        assert!(adjacency.iter().map(|(point, _)| point.data).eq(qt.iter().map(|point| point.data)));
+        let neighbours: HashMap<i32, Vec<i32>> = adjacency.iter()
+            .map(|(point, list)| (point.data, sorted_data(list)))
+            .collect();
+
+        let all = qt.collect();
+        for a in &all {
+            let brute: Vec<i32> = sorted_data(&all.iter()
+                .filter(|b| b.data != a.data && (a.x - b.x).powi(2) + (a.y - b.y).powi(2) < radius * radius)
+                .cloned()
+                .collect::<Vec<_>>());
+            assert_eq!(neighbours[&a.data], brute);
+            assert!(brute.iter().all(|b| neighbours[b].contains(&a.data)));
+        }
+
+        let degree_sum: usize = neighbours.values().map(|list| list.len()).sum();
+        assert_eq!(qt.edge_count(radius), degree_sum / 2);
+        assert_eq!(qt.edge_count(radius), qt.pairs_within(radius).len());
     }
 
 }